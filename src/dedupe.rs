@@ -0,0 +1,327 @@
+//! Duplicate and near-duplicate document detection.
+//!
+//! Files with identical bytes are exact duplicates. Near-duplicates are found from
+//! the extracted text, so a PDF export of a DOCX is recognized: each text is reduced
+//! to its words, split into overlapping runs of words (shingles), and summarized by
+//! a MinHash signature whose agreement estimates the Jaccard similarity of the
+//! shingle sets. Locality-sensitive hashing over bands of the signatures keeps the
+//! comparisons to likely pairs instead of every pair of files.
+
+use crate::fold::Folding;
+use crate::types::FileType;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Number of words in a shingle.
+const SHINGLE_WORDS: usize = 5;
+/// Number of hash functions in a MinHash signature.
+const SIGNATURE_LEN: usize = 128;
+/// Signature rows per LSH band (so `SIGNATURE_LEN / BAND_ROWS` bands).
+const BAND_ROWS: usize = 4;
+
+/// What is known about one file for duplicate detection.
+#[derive(Debug, Clone)]
+pub struct Fingerprint {
+    pub path: PathBuf,
+    pub file_type: FileType,
+    /// SHA-256 of the file's bytes (None if the file could not be read or is
+    /// empty, so empty `__init__.py` or `.gitkeep` files are not duplicates).
+    content_hash: Option<[u8; 32]>,
+    /// MinHash signature of the text's shingles (None if the text is too short).
+    signature: Option<Vec<u64>>,
+}
+
+/// Files that are copies of each other.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    /// The files, each with its estimated similarity to the first one (0.0 - 1.0).
+    pub files: Vec<ClusterFile>,
+    /// Whether all files are byte-for-byte identical.
+    pub exact: bool,
+}
+
+/// A file in a [`Cluster`].
+#[derive(Debug, Clone)]
+pub struct ClusterFile {
+    pub path: PathBuf,
+    pub file_type: FileType,
+    /// Estimated text similarity to the cluster's first file.
+    pub similarity: f64,
+    /// Whether the file is byte-for-byte identical to the first file.
+    pub identical: bool,
+}
+
+impl Fingerprint {
+    /// Fingerprint a file from its bytes on disk and its extracted text (empty
+    /// if none could be extracted, as for a scan without OCR).
+    pub fn new(path: &Path, file_type: FileType, text: &str) -> Self {
+        let content_hash = fs::read(path)
+            .ok()
+            .filter(|bytes| !bytes.is_empty())
+            .map(|bytes| Sha256::digest(bytes).into());
+        Self {
+            path: path.to_path_buf(),
+            file_type,
+            content_hash,
+            signature: minhash(text),
+        }
+    }
+
+    /// Estimated Jaccard similarity of two files' texts.
+    fn similarity(&self, other: &Fingerprint) -> f64 {
+        match (&self.signature, &other.signature) {
+            (Some(a), Some(b)) => {
+                let agree = a.iter().zip(b).filter(|(x, y)| x == y).count();
+                agree as f64 / SIGNATURE_LEN as f64
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn identical(&self, other: &Fingerprint) -> bool {
+        self.content_hash.is_some() && self.content_hash == other.content_hash
+    }
+}
+
+/// MinHash signature of a text's word shingles, ignoring case, accents, punctuation
+/// and layout. Texts shorter than one shingle get no signature.
+fn minhash(text: &str) -> Option<Vec<u64>> {
+    let folded = Folding {
+        case: true,
+        accents: true,
+    }
+    .fold(text);
+    let words: Vec<&str> = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.len() < SHINGLE_WORDS {
+        return None;
+    }
+
+    let mut signature = vec![u64::MAX; SIGNATURE_LEN];
+    for shingle in words.windows(SHINGLE_WORDS) {
+        let mut hasher = DefaultHasher::new();
+        shingle.hash(&mut hasher);
+        let base = hasher.finish();
+        for (seed, min) in signature.iter_mut().enumerate() {
+            *min = (*min).min(mix(base ^ (seed as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)));
+        }
+    }
+    Some(signature)
+}
+
+/// SplitMix64 finalizer, turning one hash into independent-looking variants.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// Group the files into clusters of exact duplicates and of near-duplicates whose
+/// texts are at least `threshold` similar. Clusters are returned largest first.
+pub fn find_duplicates(mut fingerprints: Vec<Fingerprint>, threshold: f64) -> Vec<Cluster> {
+    fingerprints.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut groups = UnionFind::new(fingerprints.len());
+
+    // Exact duplicates share a content hash
+    let mut by_hash: HashMap<[u8; 32], usize> = HashMap::new();
+    for (i, fingerprint) in fingerprints.iter().enumerate() {
+        if let Some(hash) = fingerprint.content_hash {
+            match by_hash.get(&hash) {
+                Some(&first) => groups.union(first, i),
+                None => {
+                    by_hash.insert(hash, i);
+                }
+            }
+        }
+    }
+
+    // Near-duplicates: compare files that agree on a whole band of their signatures
+    let mut buckets: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+    for (i, fingerprint) in fingerprints.iter().enumerate() {
+        if let Some(signature) = &fingerprint.signature {
+            for (band, rows) in signature.chunks(BAND_ROWS).enumerate() {
+                buckets.entry((band, rows)).or_default().push(i);
+            }
+        }
+    }
+    for bucket in buckets.values() {
+        for (n, &i) in bucket.iter().enumerate() {
+            for &j in &bucket[n + 1..] {
+                if groups.find(i) != groups.find(j)
+                    && fingerprints[i].similarity(&fingerprints[j]) >= threshold
+                {
+                    groups.union(i, j);
+                }
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..fingerprints.len() {
+        members.entry(groups.find(i)).or_default().push(i);
+    }
+    let mut clusters: Vec<Cluster> = members
+        .into_values()
+        .filter(|m| m.len() > 1)
+        .map(|m| {
+            let first = &fingerprints[m[0]];
+            let files: Vec<ClusterFile> = m
+                .iter()
+                .map(|&i| {
+                    let fingerprint = &fingerprints[i];
+                    let identical = fingerprint.identical(first);
+                    ClusterFile {
+                        path: fingerprint.path.clone(),
+                        file_type: fingerprint.file_type,
+                        similarity: if identical {
+                            1.0
+                        } else {
+                            fingerprint.similarity(first)
+                        },
+                        identical,
+                    }
+                })
+                .collect();
+            Cluster {
+                exact: files.iter().all(|f| f.identical),
+                files,
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.files
+            .len()
+            .cmp(&a.files.len())
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+    });
+    clusters
+}
+
+/// Disjoint sets over file indices.
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        self.parent[i] = root;
+        root
+    }
+
+    /// Merge two sets, keeping the smaller index as the root so clusters start
+    /// with their first file by path.
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const REPORT: &str = "The quarterly report shows revenue growth in all regions, \
+        with the strongest results in the northern division and steady \
+        performance in the southern offices despite higher costs.";
+
+    #[test]
+    fn test_similarity_ignores_layout() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "").unwrap();
+        let original = Fingerprint::new(&path, FileType::Docx, REPORT);
+
+        // A PDF export wraps lines and changes case and punctuation
+        let exported = REPORT.to_uppercase().replace(", ", "\n").replace(' ', "  ");
+        let export = Fingerprint::new(&path, FileType::Pdf, &exported);
+        assert_eq!(original.similarity(&export), 1.0);
+
+        let edited = REPORT.replace("northern", "eastern");
+        let edit = Fingerprint::new(&path, FileType::Text, &edited);
+        let similarity = original.similarity(&edit);
+        assert!(similarity > 0.5 && similarity < 1.0, "{}", similarity);
+
+        let unrelated = Fingerprint::new(
+            &path,
+            FileType::Text,
+            "An entirely different memo about parking spaces and office hours",
+        );
+        assert!(original.similarity(&unrelated) < 0.1);
+        assert!(minhash("too short").is_none());
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+        let a = write("report.txt", REPORT);
+        let b = write("report (1).txt", REPORT);
+        let c = write("report.md", &format!("# Report\n\n{}", REPORT));
+        let d = write(
+            "notes.txt",
+            "Minutes of the weekly meeting about the new parking rules",
+        );
+        let e = write(
+            "notes_copy.txt",
+            "Minutes of the weekly meeting about the new parking rules",
+        );
+        // Empty files all hash the same but are not copies of each other
+        let f = write("__init__.py", "");
+        let g = write(".gitkeep", "");
+        // Copies of a scan are exact duplicates even without text
+        let h = write("scan.png", "\u{89}PNG pixels");
+        let i = write("scan (1).png", "\u{89}PNG pixels");
+
+        let fingerprints = vec![
+            Fingerprint::new(&a, FileType::Text, REPORT),
+            Fingerprint::new(&b, FileType::Text, REPORT),
+            Fingerprint::new(&c, FileType::Text, &fs::read_to_string(&c).unwrap()),
+            Fingerprint::new(&d, FileType::Text, &fs::read_to_string(&d).unwrap()),
+            Fingerprint::new(&e, FileType::Text, &fs::read_to_string(&e).unwrap()),
+            Fingerprint::new(&f, FileType::Text, ""),
+            Fingerprint::new(&g, FileType::Text, ""),
+            Fingerprint::new(&h, FileType::Image, ""),
+            Fingerprint::new(&i, FileType::Image, ""),
+        ];
+        let mut clusters = find_duplicates(fingerprints, 0.8);
+        assert_eq!(clusters.len(), 3);
+
+        let scans = clusters
+            .iter()
+            .position(|c| c.files[0].file_type == FileType::Image)
+            .unwrap();
+        let scans = clusters.remove(scans);
+        assert!(scans.exact);
+        assert_eq!(scans.files.len(), 2);
+
+        let near = &clusters[0];
+        assert!(!near.exact);
+        let paths: Vec<&Path> = near.files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, vec![b.as_path(), c.as_path(), a.as_path()]);
+        assert!(near.files[2].identical);
+        assert!(!near.files[1].identical && near.files[1].similarity >= 0.8);
+
+        assert!(clusters[1].exact);
+        assert_eq!(clusters[1].files.len(), 2);
+    }
+}
@@ -1,16 +1,34 @@
 //! Text extraction from various file formats.
 
+use crate::ocr_cache::OcrCache;
 use crate::types::FileType;
 use anyhow::{Context, Result};
 use encoding_rs::UTF_8;
 use encoding_rs_io::DecodeReaderBytesBuilder;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Maximum file size to read (50 MB).
 const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
 
+/// Error message used when the OCR budget has run out.
+pub const OCR_BUDGET_EXHAUSTED: &str = "OCR budget exhausted";
+
+/// Error message for images when OCR is off.
+pub const OCR_NOT_ENABLED: &str = "OCR not enabled for images";
+
+/// Error message for OCR when Argus was built without the `ocr` feature.
+pub const OCR_NOT_BUILT: &str = "OCR feature not enabled. Rebuild with --features ocr";
+
+/// Tesseract language used for OCR.
+pub const OCR_LANGUAGE: &str = "eng";
+
 /// Result of text extraction.
 #[derive(Debug)]
 pub struct ExtractionResult {
@@ -20,6 +38,8 @@ pub struct ExtractionResult {
     pub success: bool,
     /// Error message if any.
     pub error: Option<String>,
+    /// Whether some content was left out (e.g. pages not OCR'd because the budget ran out).
+    pub partial: bool,
 }
 
 impl ExtractionResult {
@@ -29,6 +49,15 @@ impl ExtractionResult {
             text,
             success: true,
             error: None,
+            partial: false,
+        }
+    }
+
+    /// Create a successful extraction result that is missing some content.
+    pub fn partial(text: String) -> Self {
+        Self {
+            partial: true,
+            ..Self::success(text)
         }
     }
 
@@ -38,12 +67,192 @@ impl ExtractionResult {
             text: String::new(),
             success: false,
             error: Some(error),
+            partial: false,
+        }
+    }
+}
+
+/// Shared limits on OCR work for a single search run.
+#[derive(Debug)]
+pub struct OcrBudget {
+    /// Maximum number of pages (images or PDF pages) to OCR.
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    max_pages: Option<usize>,
+    /// Point in time after which no new OCR work is started.
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    deadline: Option<Instant>,
+    /// Pages OCR'd so far.
+    pages_used: AtomicUsize,
+}
+
+impl OcrBudget {
+    /// Create a budget starting now.
+    pub fn new(max_pages: Option<usize>, time_limit: Option<Duration>) -> Self {
+        Self {
+            max_pages,
+            deadline: time_limit.map(|limit| Instant::now() + limit),
+            pages_used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Create a budget without any limits.
+    pub fn unlimited() -> Self {
+        Self::new(None, None)
+    }
+
+    /// Claim one page of OCR work. Returns false once the budget is exhausted.
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    pub fn try_claim_page(&self) -> bool {
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return false;
+        }
+        match self.max_pages {
+            Some(max) => self
+                .pages_used
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                    (used < max).then_some(used + 1)
+                })
+                .is_ok(),
+            None => {
+                self.pages_used.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+        }
+    }
+
+    /// Number of pages OCR'd so far.
+    pub fn pages_used(&self) -> usize {
+        self.pages_used.load(Ordering::Relaxed)
+    }
+}
+
+/// Options controlling how text is extracted from files.
+#[derive(Debug, Clone)]
+pub struct ExtractionOptions {
+    /// Whether OCR is enabled for images and scanned PDFs.
+    pub ocr_enabled: bool,
+    /// OCR limits shared by every extraction in the run.
+    pub ocr_budget: Arc<OcrBudget>,
+    /// Maximum time to spend extracting a single file.
+    pub timeout: Option<Duration>,
+    /// Shared cache of OCR output, if enabled.
+    pub ocr_cache: Option<Arc<OcrCache>>,
+}
+
+impl Default for ExtractionOptions {
+    fn default() -> Self {
+        Self {
+            ocr_enabled: false,
+            ocr_budget: Arc::new(OcrBudget::unlimited()),
+            timeout: None,
+            ocr_cache: None,
         }
     }
 }
 
+/// A unit of extraction work run on a helper thread.
+type ExtractionJob = Box<dyn FnOnce() + Send>;
+
+/// A long-lived helper thread that runs extractions for one search thread.
+///
+/// Keeping the helper alive between files preserves its thread-local state
+/// (the Tesseract instance). A helper that overruns the timeout is abandoned:
+/// its queue is dropped, so it exits once the stuck extraction returns.
+struct ExtractionWorker {
+    jobs: mpsc::Sender<ExtractionJob>,
+}
+
+impl ExtractionWorker {
+    fn spawn() -> Option<Self> {
+        let (jobs, queue) = mpsc::channel::<ExtractionJob>();
+        std::thread::Builder::new()
+            .name("argus-extract".to_string())
+            .spawn(move || {
+                for job in queue {
+                    job();
+                }
+            })
+            .ok()?;
+        Some(Self { jobs })
+    }
+}
+
+/// Extract text from a file, giving up once `options.timeout` has elapsed.
+///
+/// Without a timeout this is the same as [`extract_text`]. With one, the
+/// extraction runs on a helper thread so a pathological file cannot hold the
+/// calling thread; a file that overruns is reported as a failure.
+pub fn extract_text_with_timeout(
+    path: &Path,
+    file_type: FileType,
+    options: &ExtractionOptions,
+) -> ExtractionResult {
+    let timeout = match options.timeout {
+        Some(t) => t,
+        None => return extract_text(path, file_type, options),
+    };
+
+    thread_local! {
+        static WORKER: std::cell::RefCell<Option<ExtractionWorker>> = const { std::cell::RefCell::new(None) };
+    }
+
+    let (reply, result) = mpsc::channel();
+    let job_path: PathBuf = path.to_path_buf();
+    let job_options = options.clone();
+    let job: ExtractionJob = Box::new(move || {
+        let _ = reply.send(extract_text(&job_path, file_type, &job_options));
+    });
+
+    let queued = WORKER.with(|cell| {
+        let mut worker = cell.borrow_mut();
+        if worker.is_none() {
+            *worker = ExtractionWorker::spawn();
+        }
+        match worker.as_ref() {
+            Some(w) => w.jobs.send(job).is_ok(),
+            None => false,
+        }
+    });
+    if !queued {
+        WORKER.with(|cell| cell.borrow_mut().take());
+        return extract_text(path, file_type, options);
+    }
+
+    match result.recv_timeout(timeout) {
+        Ok(extraction) => extraction,
+        Err(RecvTimeoutError::Timeout) => {
+            // Abandon the stuck helper; the next file gets a fresh one
+            WORKER.with(|cell| cell.borrow_mut().take());
+            ExtractionResult::failure(format!(
+                "Extraction timed out after {}",
+                format_duration(timeout)
+            ))
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            WORKER.with(|cell| cell.borrow_mut().take());
+            ExtractionResult::failure("Extraction aborted unexpectedly".to_string())
+        }
+    }
+}
+
+/// Format a duration as whole seconds, or milliseconds when under a second.
+fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() > 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
 /// Extract text from a file based on its type.
-pub fn extract_text(path: &Path, file_type: FileType, ocr_enabled: bool) -> ExtractionResult {
+pub fn extract_text(
+    path: &Path,
+    file_type: FileType,
+    options: &ExtractionOptions,
+) -> ExtractionResult {
     // Check file size first
     if let Ok(metadata) = path.metadata() {
         if metadata.len() > MAX_FILE_SIZE {
@@ -57,13 +266,14 @@ pub fn extract_text(path: &Path, file_type: FileType, ocr_enabled: bool) -> Extr
 
     match file_type {
         FileType::Text | FileType::Code | FileType::Other => extract_text_file(path),
-        FileType::Pdf => extract_pdf(path, ocr_enabled),
-        FileType::Docx => extract_docx(path),
+        FileType::Pdf => extract_pdf(path, options),
+        FileType::Docx => extract_docx(path, options),
+        FileType::Pptx => extract_pptx(path, options),
         FileType::Image => {
-            if ocr_enabled {
-                extract_image_ocr(path)
+            if options.ocr_enabled {
+                extract_image_ocr(path, options)
             } else {
-                ExtractionResult::failure("OCR not enabled for images".to_string())
+                ExtractionResult::failure(OCR_NOT_ENABLED.to_string())
             }
         }
     }
@@ -109,306 +319,326 @@ fn extract_text_file(path: &Path) -> ExtractionResult {
     ExtractionResult::success(text)
 }
 
+/// Separator placed between pages of extracted PDF text (a form feed, as in `pdftotext`).
+pub const PAGE_BREAK: char = '\u{0C}';
+
+/// Pages with less text than this are treated as scanned and OCR'd when OCR is enabled.
+/// A scanned page typically yields < 100 chars of garbage from pdf-extract.
+#[cfg(feature = "ocr")]
+const SCANNED_PAGE_TEXT_LEN: usize = 100;
+
 /// Extract text from a PDF file.
-/// When `ocr_enabled` is true, falls back to OCR on embedded images if text extraction
-/// yields very little content (indicating a scanned/image-based PDF).
-fn extract_pdf(path: &Path, ocr_enabled: bool) -> ExtractionResult {
+/// The text layer is extracted page by page. When OCR is enabled, pages with very
+/// little text (indicating a scanned/image-based page) are rendered and OCR'd, and
+/// the OCR text is placed with the page it came from.
+fn extract_pdf(path: &Path, options: &ExtractionOptions) -> ExtractionResult {
     // First try normal text extraction
-    let text_result = pdf_extract::extract_text(path);
-
-    let cleaned = match text_result {
-        Ok(text) => {
-            text.lines()
-                .map(|line| line.trim())
-                .filter(|line| !line.is_empty())
-                .collect::<Vec<_>>()
-                .join("\n")
-        }
-        Err(_) => String::new(),
+    #[allow(unused_mut)]
+    let mut pages: Vec<String> = match pdf_extract::extract_text_by_pages(path) {
+        Ok(pages) => pages.iter().map(|page| clean_lines(page)).collect(),
+        Err(_) => Vec::new(),
     };
 
-    // If we got substantial text, return it
-    // A scanned PDF typically yields < 100 chars of garbage from pdf-extract
-    let has_substantial_text = cleaned.len() > 100;
+    #[cfg(feature = "ocr")]
+    if options.ocr_enabled {
+        let is_sparse = |page: u32| {
+            pages
+                .get(page as usize - 1)
+                .is_none_or(|text| text.len() < SCANNED_PAGE_TEXT_LEN)
+        };
 
-    if has_substantial_text || !ocr_enabled {
-        if cleaned.is_empty() {
-            return ExtractionResult::failure("Failed to extract PDF text".to_string());
+        let mut budget_exhausted = false;
+        match crate::pdf_ocr::ocr_pdf_pages(path, pages.len() as u32, is_sparse, options) {
+            Ok(ocr) => {
+                budget_exhausted = ocr.budget_exhausted;
+                for (page, text) in ocr.pages {
+                    let idx = page as usize - 1;
+                    if pages.len() <= idx {
+                        pages.resize(idx + 1, String::new());
+                    }
+                    // Combine any sparse text with the page's OCR text
+                    if pages[idx].is_empty() {
+                        pages[idx] = text;
+                    } else {
+                        pages[idx] = format!("{}\n{}", pages[idx], text);
+                    }
+                }
+            }
+            Err(e) => {
+                if pages.iter().all(|p| p.is_empty()) {
+                    return ExtractionResult::failure(e);
+                }
+            }
         }
-        return ExtractionResult::success(cleaned);
-    }
 
-    // OCR fallback: try extracting text from embedded images in the PDF
-    #[cfg(feature = "ocr")]
-    {
-        let ocr_result = extract_pdf_images_ocr(path);
-        if ocr_result.success && !ocr_result.text.is_empty() {
-            // Combine any sparse text with OCR text
-            if cleaned.is_empty() {
-                return ocr_result;
+        if pages.iter().all(|p| p.is_empty()) {
+            if budget_exhausted {
+                return ExtractionResult::failure(OCR_BUDGET_EXHAUSTED.to_string());
             }
-            let combined = format!("{}\n{}", cleaned, ocr_result.text);
-            return ExtractionResult::success(combined);
+            return ExtractionResult::failure(
+                "PDF appears to be scanned but OCR could not extract text".to_string(),
+            );
         }
-
-        // If OCR also failed but we have some text, return what we have
-        if !cleaned.is_empty() {
-            return ExtractionResult::success(cleaned);
+        if budget_exhausted {
+            return ExtractionResult::partial(join_pages(&pages));
         }
-        return ExtractionResult::failure(
-            "PDF appears to be scanned but OCR could not extract text".to_string(),
-        );
+        return ExtractionResult::success(join_pages(&pages));
     }
 
-    #[cfg(not(feature = "ocr"))]
-    {
-        if cleaned.is_empty() {
-            ExtractionResult::failure(
+    if pages.iter().all(|p| p.is_empty()) {
+        if options.ocr_enabled {
+            return ExtractionResult::failure(
                 "PDF appears to be scanned. Rebuild with --features ocr for OCR support"
                     .to_string(),
-            )
-        } else {
-            ExtractionResult::success(cleaned)
+            );
         }
+        return ExtractionResult::failure("Failed to extract PDF text".to_string());
     }
-}
-
-/// Extract text from embedded images in a PDF using OCR.
-/// This handles scanned PDFs where pages are stored as images.
-#[cfg(feature = "ocr")]
-fn extract_pdf_images_ocr(path: &Path) -> ExtractionResult {
-    use lopdf::{Document, Object};
-
-    let doc = match Document::load(path) {
-        Ok(d) => d,
-        Err(e) => {
-            return ExtractionResult::failure(format!("Failed to parse PDF for OCR: {}", e))
-        }
-    };
-
-    let mut all_text: Vec<String> = Vec::new();
-    let mut image_count = 0;
 
-    // Iterate through all objects looking for image streams
-    for (_object_id, object) in &doc.objects {
-        let stream = match object {
-            Object::Stream(ref s) => s,
-            _ => continue,
-        };
+    ExtractionResult::success(join_pages(&pages))
+}
 
-        // Check if this is an Image XObject
-        let is_image = stream
-            .dict
-            .get(b"Subtype")
-            .map(|s| matches!(s, Object::Name(ref n) if n == b"Image"))
-            .unwrap_or(false);
+/// Trim every line and drop empty ones.
+fn clean_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-        if !is_image {
-            continue;
-        }
+/// Join per-page text, putting a page break on its own line between pages.
+fn join_pages(pages: &[String]) -> String {
+    pages.join(&format!("\n{}\n", PAGE_BREAK))
+}
 
-        // Get image dimensions
-        let width = match stream.dict.get(b"Width") {
-            Ok(Object::Integer(w)) => *w as u32,
-            _ => continue,
-        };
-        let height = match stream.dict.get(b"Height") {
-            Ok(Object::Integer(h)) => *h as u32,
-            _ => continue,
-        };
+/// Extract text from a DOCX file.
+/// When OCR is enabled, text in embedded images is placed where the image appears.
+fn extract_docx(path: &Path, options: &ExtractionOptions) -> ExtractionResult {
+    match extract_docx_text(path, options) {
+        Ok((text, false)) => ExtractionResult::success(text),
+        Ok((text, true)) => ExtractionResult::partial(text),
+        Err(e) => ExtractionResult::failure(format!("Failed to extract DOCX text: {}", e)),
+    }
+}
 
-        // Skip very small images (icons, thumbnails, etc.)
-        if width < 100 || height < 100 {
-            continue;
-        }
+/// Internal DOCX text extraction using zip and xml parsing.
+/// Also returns whether embedded images were skipped because the OCR budget ran out.
+fn extract_docx_text(path: &Path, options: &ExtractionOptions) -> Result<(String, bool)> {
+    let file = File::open(path).context("Failed to open DOCX file")?;
+    let mut archive = zip::ZipArchive::new(file).context("Failed to read DOCX as ZIP")?;
 
-        // Determine the image filter (compression type)
-        let filters = get_stream_filters(&stream.dict);
+    // DOCX files store the main content in word/document.xml
+    office_part_text(&mut archive, "word/document.xml", "w", options)
+}
 
-        // Try to extract and OCR this image
-        if let Some(temp_file) = extract_image_from_pdf_stream(stream, &filters, width, height) {
-            let ocr_result = extract_image_ocr(temp_file.path());
-            if ocr_result.success && !ocr_result.text.trim().is_empty() {
-                all_text.push(ocr_result.text);
-                image_count += 1;
-            }
-        }
+/// Extract text from a PPTX file, slide by slide.
+/// When OCR is enabled, text in embedded images is placed where the image appears.
+fn extract_pptx(path: &Path, options: &ExtractionOptions) -> ExtractionResult {
+    match extract_pptx_text(path, options) {
+        Ok((text, false)) => ExtractionResult::success(text),
+        Ok((text, true)) => ExtractionResult::partial(text),
+        Err(e) => ExtractionResult::failure(format!("Failed to extract PPTX text: {}", e)),
     }
+}
 
-    if all_text.is_empty() {
-        ExtractionResult::failure(format!(
-            "No readable text found in {} PDF image(s)",
-            image_count
-        ))
-    } else {
-        ExtractionResult::success(all_text.join("\n\n"))
+/// Internal PPTX text extraction.
+/// Also returns whether embedded images were skipped because the OCR budget ran out.
+fn extract_pptx_text(path: &Path, options: &ExtractionOptions) -> Result<(String, bool)> {
+    let file = File::open(path).context("Failed to open PPTX file")?;
+    let mut archive = zip::ZipArchive::new(file).context("Failed to read PPTX as ZIP")?;
+
+    let presentation = read_zip_entry(&mut archive, "ppt/presentation.xml")
+        .context("Failed to find presentation.xml in PPTX")?;
+
+    let mut slides = Vec::new();
+    let mut budget_exhausted = false;
+    for slide in pptx_slides(&mut archive, &presentation) {
+        let (text, exhausted) = office_part_text(&mut archive, &slide, "a", options)?;
+        slides.push(text);
+        budget_exhausted |= exhausted;
     }
+    Ok((slides.join("\n"), budget_exhausted))
 }
 
-/// Get the list of filters applied to a PDF stream.
-#[cfg(feature = "ocr")]
-fn get_stream_filters(dict: &lopdf::Dictionary) -> Vec<Vec<u8>> {
-    use lopdf::Object;
-
-    match dict.get(b"Filter") {
-        Ok(Object::Name(n)) => vec![n.clone()],
-        Ok(Object::Array(arr)) => arr
-            .iter()
-            .filter_map(|o| {
-                if let Object::Name(n) = o {
-                    Some(n.clone())
-                } else {
-                    None
-                }
-            })
-            .collect(),
-        _ => vec![],
+/// Archive paths of a presentation's slides, in the order they are shown.
+fn pptx_slides(archive: &mut zip::ZipArchive<File>, presentation: &str) -> Vec<String> {
+    let targets = read_zip_entry(archive, "ppt/_rels/presentation.xml.rels")
+        .map(|rels| relationship_targets(&rels, "/slide", "ppt"))
+        .unwrap_or_default();
+    let ordered: Vec<String> = presentation
+        .split('<')
+        .filter(|t| t.starts_with("p:sldId "))
+        .filter_map(|t| targets.get(xml_attribute(t, "r:id")?).cloned())
+        .collect();
+    if !ordered.is_empty() {
+        return ordered;
     }
+
+    // Without a slide list, go by the numbers in the slides' names
+    let mut numbered: Vec<(usize, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let number = name.strip_prefix("ppt/slides/slide")?.strip_suffix(".xml")?;
+            Some((number.parse().ok()?, name.to_string()))
+        })
+        .collect();
+    numbered.sort();
+    numbered.into_iter().map(|(_, name)| name).collect()
 }
 
-/// Extract an image from a PDF stream and save to a temporary file.
-/// Returns None if the image format is unsupported or extraction fails.
-#[cfg(feature = "ocr")]
-fn extract_image_from_pdf_stream(
-    stream: &lopdf::Stream,
-    filters: &[Vec<u8>],
-    width: u32,
-    height: u32,
-) -> Option<tempfile::NamedTempFile> {
-    use image::{DynamicImage, GrayImage, RgbImage};
-    use lopdf::Object;
-    use std::io::Write;
+/// Text of one XML part of a DOCX or PPTX archive, whose text runs and paragraphs
+/// use the namespace prefix `ns`. When OCR is enabled, text in embedded images is
+/// placed where the image appears. Also returns whether images were skipped because
+/// the OCR budget ran out.
+fn office_part_text(
+    archive: &mut zip::ZipArchive<File>,
+    part: &str,
+    ns: &str,
+    options: &ExtractionOptions,
+) -> Result<(String, bool)> {
+    let xml = read_zip_entry(archive, part).with_context(|| format!("Failed to find {}", part))?;
 
-    let is_dct = filters.iter().any(|f| f == b"DCTDecode");
-    let is_jpx = filters.iter().any(|f| f == b"JPXDecode");
-    let is_flate = filters.iter().any(|f| f == b"FlateDecode");
+    #[cfg(feature = "ocr")]
+    if options.ocr_enabled {
+        return Ok(ocr_office_part(archive, part, &xml, ns, options));
+    }
+    #[cfg(not(feature = "ocr"))]
+    let _ = options;
 
-    if is_dct {
-        // DCTDecode = JPEG: the stream content is a valid JPEG file
-        let mut temp = tempfile::Builder::new()
-            .suffix(".jpg")
-            .tempfile()
-            .ok()?;
-        temp.write_all(&stream.content).ok()?;
-        temp.flush().ok()?;
-        Some(temp)
-    } else if is_jpx {
-        // JPXDecode = JPEG2000: save the raw stream as .jp2
-        let mut temp = tempfile::Builder::new()
-            .suffix(".jp2")
-            .tempfile()
-            .ok()?;
-        temp.write_all(&stream.content).ok()?;
-        temp.flush().ok()?;
-        Some(temp)
-    } else if is_flate || filters.is_empty() {
-        // FlateDecode or uncompressed: raw pixel data that needs reconstruction
-        let mut stream_clone = stream.clone();
-        stream_clone.decompress();
-        let raw_data = stream_clone.content;
-
-        // Determine color depth
-        let bpc = match stream.dict.get(b"BitsPerComponent") {
-            Ok(Object::Integer(b)) => *b as u8,
-            _ => 8,
-        };
+    Ok((extract_text_from_office_xml(&xml, ns, |_| None), false))
+}
 
-        if bpc != 8 {
-            return None; // Only handle 8-bit images for now
+/// [`office_part_text`] with OCR of the embedded images.
+#[cfg(feature = "ocr")]
+fn ocr_office_part(
+    archive: &mut zip::ZipArchive<File>,
+    part: &str,
+    xml: &str,
+    ns: &str,
+    options: &ExtractionOptions,
+) -> (String, bool) {
+    // Images are referenced by relationship id; resolve them to media files
+    let (dir, name) = part.rsplit_once('/').unwrap_or(("", part));
+    let images = read_zip_entry(archive, &format!("{}/_rels/{}.rels", dir, name))
+        .map(|rels| relationship_targets(&rels, "/image", dir))
+        .unwrap_or_default();
+
+    let mut budget_exhausted = false;
+    let mut recognised: HashMap<String, Option<String>> = HashMap::new();
+    let text = extract_text_from_office_xml(xml, ns, |id| {
+        if let Some(text) = recognised.get(id) {
+            return text.clone();
         }
-
-        // Determine color space (DeviceGray=1ch, DeviceRGB=3ch)
-        let channels = get_color_channels(&stream.dict);
-        let expected_size = (width as usize) * (height as usize) * (channels as usize);
-
-        if raw_data.len() < expected_size {
-            return None; // Data doesn't match expected dimensions
+        let target = images.get(id)?;
+        let result = ocr_archive_image(archive, target, options);
+        if result.error.as_deref() == Some(OCR_BUDGET_EXHAUSTED) {
+            budget_exhausted = true;
         }
+        let text = result.success.then_some(result.text);
+        recognised.insert(id.to_string(), text.clone());
+        text
+    });
 
-        // Construct image from raw pixels
-        let img = match channels {
-            1 => {
-                let gray = GrayImage::from_raw(width, height, raw_data)?;
-                DynamicImage::ImageLuma8(gray)
-            }
-            3 => {
-                let rgb = RgbImage::from_raw(width, height, raw_data)?;
-                DynamicImage::ImageRgb8(rgb)
-            }
-            _ => return None,
-        };
+    (text, budget_exhausted)
+}
 
-        let temp = tempfile::Builder::new()
-            .suffix(".png")
-            .tempfile()
-            .ok()?;
-        img.save(temp.path()).ok()?;
-        Some(temp)
-    } else {
-        None // Unsupported filter (CCITT, JBIG2, etc.)
+/// Read a ZIP entry as a string.
+fn read_zip_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<String> {
+    let mut entry = archive.by_name(name)?;
+    let mut content = String::new();
+    entry
+        .read_to_string(&mut content)
+        .with_context(|| format!("Failed to read {}", name))?;
+    Ok(content)
+}
+
+/// Map the ids of internal relationships whose type ends with `kind` (`/image`,
+/// `/slide`) to archive paths, from the `.rels` file of a part in the directory `dir`.
+fn relationship_targets(rels: &str, kind: &str, dir: &str) -> HashMap<String, String> {
+    let mut targets = HashMap::new();
+    for tag in rels.split('<').filter(|t| t.starts_with("Relationship ")) {
+        let is_kind = xml_attribute(tag, "Type").is_some_and(|t| t.ends_with(kind));
+        let is_external = xml_attribute(tag, "TargetMode") == Some("External");
+        if !is_kind || is_external {
+            continue;
+        }
+        if let (Some(id), Some(target)) = (xml_attribute(tag, "Id"), xml_attribute(tag, "Target")) {
+            targets.insert(id.to_string(), resolve_target(dir, target));
+        }
     }
+    targets
 }
 
-/// Determine the number of color channels from a PDF image's ColorSpace.
-#[cfg(feature = "ocr")]
-fn get_color_channels(dict: &lopdf::Dictionary) -> u8 {
-    use lopdf::Object;
-
-    match dict.get(b"ColorSpace") {
-        Ok(Object::Name(ref name)) => match name.as_slice() {
-            b"DeviceGray" | b"CalGray" => 1,
-            b"DeviceRGB" | b"CalRGB" => 3,
-            b"DeviceCMYK" => 4,
-            _ => 3, // Default to RGB
-        },
-        Ok(Object::Array(ref arr)) => {
-            // Indexed or ICCBased color spaces are arrays like [/ICCBased ref]
-            if let Some(Object::Name(ref name)) = arr.first() {
-                match name.as_slice() {
-                    b"ICCBased" => 3, // Most common ICC profiles are RGB
-                    b"Indexed" => 1,  // Palette-based
-                    b"CalGray" => 1,
-                    b"CalRGB" => 3,
-                    _ => 3,
-                }
-            } else {
-                3
+/// Resolve a relationship target (relative to `dir`) to an archive path.
+fn resolve_target(dir: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            ".." => {
+                parts.pop();
             }
+            "." | "" => {}
+            _ => parts.push(part),
         }
-        _ => 3, // Default to RGB if ColorSpace is missing or a reference
     }
+    parts.join("/")
 }
 
-/// Extract text from a DOCX file.
-fn extract_docx(path: &Path) -> ExtractionResult {
-    match extract_docx_text(path) {
-        Ok(text) => ExtractionResult::success(text),
-        Err(e) => ExtractionResult::failure(format!("Failed to extract DOCX text: {}", e)),
+/// Get the value of an attribute from the inside of an XML tag.
+fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(pos) = rest.find(name) {
+        let preceded_by_space = rest[..pos].ends_with(char::is_whitespace);
+        let after = &rest[pos + name.len()..];
+        if preceded_by_space {
+            if let Some(value) = after.strip_prefix("=\"") {
+                return value.split('"').next();
+            }
+        }
+        rest = after;
     }
+    None
 }
 
-/// Internal DOCX text extraction using zip and xml parsing.
-fn extract_docx_text(path: &Path) -> Result<String> {
-    let file = File::open(path).context("Failed to open DOCX file")?;
-    let mut archive = zip::ZipArchive::new(file).context("Failed to read DOCX as ZIP")?;
-
-    // DOCX files store the main content in word/document.xml
-    let mut document = archive
-        .by_name("word/document.xml")
-        .context("Failed to find document.xml in DOCX")?;
-
-    let mut xml_content = String::new();
-    document
-        .read_to_string(&mut xml_content)
-        .context("Failed to read document.xml")?;
-
-    // Parse XML and extract text content
-    let text = extract_text_from_docx_xml(&xml_content);
-    Ok(text)
+/// OCR an image stored in a DOCX or PPTX archive.
+#[cfg(feature = "ocr")]
+fn ocr_archive_image(
+    archive: &mut zip::ZipArchive<File>,
+    name: &str,
+    options: &ExtractionOptions,
+) -> ExtractionResult {
+    let mut data = Vec::new();
+    let read = archive
+        .by_name(name)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut entry| Ok(entry.read_to_end(&mut data)?));
+    if let Err(e) = read {
+        return ExtractionResult::failure(format!("Failed to read {}: {}", name, e));
+    }
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("img")
+        .to_string();
+    ocr_images(&[OcrImage { data, extension }], options)
 }
 
-/// Extract text content from DOCX XML.
-fn extract_text_from_docx_xml(xml: &str) -> String {
+/// Extract text content from DOCX or PPTX XML, whose text runs (`w:t`, `a:t`) and
+/// paragraphs (`w:p`, `a:p`) use the namespace prefix `ns`.
+/// `image_text` is called with the relationship id of each embedded image and
+/// any text it returns is inserted on its own lines at the image's position.
+fn extract_text_from_office_xml(
+    xml: &str,
+    ns: &str,
+    mut image_text: impl FnMut(&str) -> Option<String>,
+) -> String {
+    let text_tag = format!("{}:t", ns);
+    let text_end = format!("/{}:t", ns);
+    let paragraph_end = format!("/{}:p", ns);
     let mut result = String::new();
     let mut in_text = false;
     let mut current_text = String::new();
@@ -428,15 +658,24 @@ fn extract_text_from_docx_xml(xml: &str) -> String {
                 tag.push(chars.next().unwrap());
             }
 
-            if tag.starts_with("w:t") && !tag.starts_with("w:t/") {
+            let name = tag.split_whitespace().next().unwrap_or_default();
+            if name == text_tag {
                 in_text = true;
                 current_text.clear();
-            } else if tag == "/w:t" {
+            } else if name == text_end {
                 in_text = false;
                 result.push_str(&current_text);
-            } else if tag == "/w:p" || tag.starts_with("/w:p ") {
+            } else if name == paragraph_end {
                 // End of paragraph - add newline
                 result.push('\n');
+            } else if tag.starts_with("a:blip ") || tag.starts_with("v:imagedata ") {
+                // Embedded image (DrawingML or legacy VML)
+                let id = xml_attribute(&tag, "r:embed").or_else(|| xml_attribute(&tag, "r:id"));
+                if let Some(text) = id.and_then(&mut image_text) {
+                    result.push('\n');
+                    result.push_str(&text);
+                    result.push('\n');
+                }
             }
         } else if in_text {
             current_text.push(c);
@@ -453,16 +692,121 @@ fn extract_text_from_docx_xml(xml: &str) -> String {
     lines.join("\n")
 }
 
-/// Extract text from an image using OCR (Tesseract).
+/// Extract text from an image using OCR, counting it as one page against the budget.
+#[cfg(feature = "ocr")]
+fn extract_image_ocr(path: &Path, options: &ExtractionOptions) -> ExtractionResult {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return ExtractionResult::failure(format!("Failed to read image: {}", e)),
+    };
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("img")
+        .to_string();
+    ocr_images(&[OcrImage { data, extension }], options)
+}
+
+/// Stub for image OCR when the feature is disabled; nothing is OCR'd, so no page
+/// is claimed from the budget.
+#[cfg(not(feature = "ocr"))]
+fn extract_image_ocr(path: &Path, _options: &ExtractionOptions) -> ExtractionResult {
+    ocr_image_file(path)
+}
+
+/// An encoded image to OCR, held in memory so it can be looked up in the OCR cache.
+#[cfg(feature = "ocr")]
+pub struct OcrImage {
+    /// Encoded image bytes (PNG, JPEG, JPEG 2000, ...).
+    pub data: Vec<u8>,
+    /// File extension matching the encoding.
+    pub extension: String,
+}
+
+/// OCR the images making up one page, consulting the OCR cache first.
+///
+/// The page counts against the budget only if at least one image is not
+/// cached. Recognised text is joined in image order; the page fails only if
+/// none of its images could be read.
+#[cfg(feature = "ocr")]
+pub fn ocr_images(images: &[OcrImage], options: &ExtractionOptions) -> ExtractionResult {
+    let cache = options.ocr_cache.as_deref();
+    let keys: Vec<Option<String>> = images
+        .iter()
+        .map(|image| cache.map(|c| c.key(&image.data)))
+        .collect();
+    let mut texts: Vec<Option<String>> = keys
+        .iter()
+        .map(|key| cache.zip(key.as_deref()).and_then(|(c, k)| c.get(k)))
+        .collect();
+
+    let mut first_error = None;
+    if texts.iter().any(Option::is_none) {
+        if !options.ocr_budget.try_claim_page() {
+            return ExtractionResult::failure(OCR_BUDGET_EXHAUSTED.to_string());
+        }
+
+        for ((image, key), text) in images.iter().zip(&keys).zip(texts.iter_mut()) {
+            if text.is_some() {
+                continue;
+            }
+            let result = ocr_image_data(image);
+            if !result.success {
+                first_error = first_error.or(result.error);
+                continue;
+            }
+            if let (Some(c), Some(k)) = (cache, key) {
+                // A cache that cannot be written to only costs a repeat OCR later
+                let _ = c.put(k, &result.text);
+            }
+            *text = Some(result.text);
+        }
+    }
+
+    if texts.iter().all(Option::is_none) {
+        return ExtractionResult::failure(
+            first_error.unwrap_or_else(|| "OCR extraction failed".to_string()),
+        );
+    }
+
+    let text = texts
+        .into_iter()
+        .flatten()
+        .filter(|t| !t.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    ExtractionResult::success(text)
+}
+
+/// OCR an in-memory image through a temporary file.
+#[cfg(feature = "ocr")]
+fn ocr_image_data(image: &OcrImage) -> ExtractionResult {
+    use std::io::Write;
+
+    let temp = tempfile::Builder::new()
+        .suffix(&format!(".{}", image.extension))
+        .tempfile()
+        .and_then(|mut temp| {
+            temp.write_all(&image.data)?;
+            temp.flush()?;
+            Ok(temp)
+        });
+    match temp {
+        Ok(temp) => ocr_image_file(temp.path()),
+        Err(e) => ExtractionResult::failure(format!("Failed to write image for OCR: {}", e)),
+    }
+}
+
+/// Extract text from an image file using OCR (Tesseract).
 /// Uses thread-local Tesseract instances for better performance with parallel processing.
 #[cfg(feature = "ocr")]
-fn extract_image_ocr(path: &Path) -> ExtractionResult {
+fn ocr_image_file(path: &Path) -> ExtractionResult {
     use leptess::LepTess;
     use std::cell::RefCell;
 
     // Thread-local Tesseract instance to avoid re-initialization overhead
     thread_local! {
-        static TESSERACT: RefCell<Option<LepTess>> = RefCell::new(None);
+        static TESSERACT: RefCell<Option<LepTess>> = const { RefCell::new(None) };
     }
 
     TESSERACT.with(|cell| {
@@ -470,7 +814,7 @@ fn extract_image_ocr(path: &Path) -> ExtractionResult {
 
         // Initialize Tesseract if not already done for this thread
         if tess_opt.is_none() {
-            match LepTess::new(None, "eng") {
+            match LepTess::new(None, OCR_LANGUAGE) {
                 Ok(lt) => *tess_opt = Some(lt),
                 Err(e) => {
                     return ExtractionResult::failure(format!(
@@ -506,10 +850,8 @@ fn extract_image_ocr(path: &Path) -> ExtractionResult {
 
 /// Stub for OCR when feature is disabled.
 #[cfg(not(feature = "ocr"))]
-fn extract_image_ocr(_path: &Path) -> ExtractionResult {
-    ExtractionResult::failure(
-        "OCR feature not enabled. Rebuild with --features ocr".to_string(),
-    )
+fn ocr_image_file(_path: &Path) -> ExtractionResult {
+    ExtractionResult::failure(OCR_NOT_BUILT.to_string())
 }
 
 /// Check if a file is binary (non-text).
@@ -562,15 +904,169 @@ mod tests {
     #[test]
     fn test_docx_xml_extraction() {
         let xml = r#"<?xml version="1.0"?><w:document><w:body><w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:t> World</w:t></w:r></w:p><w:p><w:r><w:t>Second paragraph</w:t></w:r></w:p></w:body></w:document>"#;
-        let result = extract_text_from_docx_xml(xml);
+        let result = extract_text_from_office_xml(xml, "w", |_| None);
         assert!(result.contains("Hello World"));
         assert!(result.contains("Second paragraph"));
     }
 
+    #[test]
+    fn test_docx_image_text_at_reference() {
+        let xml = r#"<w:body><w:p><w:r><w:t>Before</w:t></w:r></w:p><w:p><w:r><w:drawing><a:blip r:embed="rId7"/></w:drawing></w:r></w:p><w:p><w:r><w:t>After</w:t></w:r></w:p></w:body>"#;
+        let result = extract_text_from_office_xml(xml, "w", |id| {
+            (id == "rId7").then(|| "Error: disk full".to_string())
+        });
+        assert_eq!(result, "Before\nError: disk full\nAfter");
+    }
+
+    #[test]
+    fn test_relationship_targets() {
+        let rels = r#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/><Relationship Id="rId7" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/><Relationship Id="rId8" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="http://example.com/a.png" TargetMode="External"/></Relationships>"#;
+        let targets = relationship_targets(rels, "/image", "word");
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets["rId7"], "word/media/image1.png");
+        assert_eq!(resolve_target("word", "../media/x.png"), "media/x.png");
+        assert_eq!(
+            resolve_target("ppt/slides", "../media/image1.png"),
+            "ppt/media/image1.png"
+        );
+    }
+
+    #[test]
+    fn test_pptx_slides_in_presentation_order() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deck.pptx");
+        let rel = |id: &str, kind: &str, target: &str| {
+            format!(
+                r#"<Relationship Id="{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/{}" Target="{}"/>"#,
+                id, kind, target
+            )
+        };
+        let slide = |text: &str| {
+            format!(
+                r#"<p:sld><p:cSld><p:spTree><p:sp><p:txBody><a:p><a:r><a:t>{}</a:t></a:r></a:p><a:p><a:r><a:t>Notes</a:t></a:r></a:p></p:txBody></p:sp></p:spTree></p:cSld></p:sld>"#,
+                text
+            )
+        };
+        let parts = [
+            (
+                "ppt/presentation.xml",
+                r#"<p:presentation><p:sldIdLst><p:sldId id="256" r:id="rId3"/><p:sldId id="257" r:id="rId2"/></p:sldIdLst></p:presentation>"#.to_string(),
+            ),
+            (
+                "ppt/_rels/presentation.xml.rels",
+                format!(
+                    "<Relationships>{}{}{}</Relationships>",
+                    rel("rId1", "slideMaster", "slideMasters/slideMaster1.xml"),
+                    rel("rId2", "slide", "slides/slide1.xml"),
+                    rel("rId3", "slide", "slides/slide2.xml")
+                ),
+            ),
+            ("ppt/slides/slide1.xml", slide("Quarterly results")),
+            ("ppt/slides/slide2.xml", slide("Agenda")),
+        ];
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, content) in &parts {
+            zip.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let result = extract_text(&path, FileType::Pptx, &ExtractionOptions::default());
+        assert!(result.success);
+        assert_eq!(result.text, "Agenda\nNotes\nQuarterly results\nNotes");
+    }
+
+    #[test]
+    fn test_pdf_pages_joined_with_page_breaks() {
+        let pages = vec![
+            "First page".to_string(),
+            String::new(),
+            "Third page".to_string(),
+        ];
+        let text = join_pages(&pages);
+        let breaks: Vec<usize> = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| *line == PAGE_BREAK.to_string())
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(breaks.len(), 2);
+        assert!(text.ends_with("Third page"));
+    }
+
+    #[test]
+    fn test_ocr_budget_page_limit() {
+        let budget = OcrBudget::new(Some(2), None);
+        assert!(budget.try_claim_page());
+        assert!(budget.try_claim_page());
+        assert!(!budget.try_claim_page());
+        assert_eq!(budget.pages_used(), 2);
+
+        let expired = OcrBudget::new(None, Some(Duration::ZERO));
+        assert!(!expired.try_claim_page());
+    }
+
+    #[cfg(not(feature = "ocr"))]
+    #[test]
+    fn test_ocr_not_built_claims_no_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.png");
+        std::fs::write(&path, b"\x89PNG").unwrap();
+
+        let options = ExtractionOptions {
+            ocr_enabled: true,
+            ..Default::default()
+        };
+        let result = extract_text(&path, FileType::Image, &options);
+        assert_eq!(result.error.as_deref(), Some(OCR_NOT_BUILT));
+        assert_eq!(options.ocr_budget.pages_used(), 0);
+    }
+
+    #[cfg(feature = "ocr")]
+    #[test]
+    fn test_cached_ocr_skips_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = OcrCache::open(dir.path().to_path_buf(), 1024 * 1024, OCR_LANGUAGE).unwrap();
+        let image = OcrImage {
+            data: b"scanned page".to_vec(),
+            extension: "png".to_string(),
+        };
+        cache.put(&cache.key(&image.data), "cached text").unwrap();
+
+        let options = ExtractionOptions {
+            ocr_enabled: true,
+            ocr_budget: Arc::new(OcrBudget::new(Some(0), None)),
+            ocr_cache: Some(Arc::new(cache)),
+            ..Default::default()
+        };
+        let result = ocr_images(&[image], &options);
+        assert!(result.success);
+        assert_eq!(result.text, "cached text");
+        assert_eq!(options.ocr_budget.pages_used(), 0);
+    }
+
+    #[test]
+    fn test_extraction_with_timeout_completes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("note.txt");
+        std::fs::write(&path, "hello").unwrap();
+
+        let options = ExtractionOptions {
+            timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let result = extract_text_with_timeout(&path, FileType::Text, &options);
+        assert!(result.success);
+        assert_eq!(result.text, "hello\n");
+    }
+
     #[test]
     fn test_file_type_detection() {
         assert_eq!(FileType::from_extension("pdf"), FileType::Pdf);
         assert_eq!(FileType::from_extension("docx"), FileType::Docx);
+        assert_eq!(FileType::from_extension("PPTX"), FileType::Pptx);
         assert_eq!(FileType::from_extension("rs"), FileType::Code);
         assert_eq!(FileType::from_extension("txt"), FileType::Text);
         assert_eq!(FileType::from_extension("png"), FileType::Image);
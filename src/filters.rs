@@ -0,0 +1,343 @@
+//! Inline file qualifiers in the search pattern.
+//!
+//! A pattern such as `invoice type:pdf path:finance/ modified:>2024-01-01 size:<5MB`
+//! is split into the text to search for (`invoice`) and filters on the files to
+//! search, which are applied while collecting files, before anything is extracted.
+//!
+//! - `type:pdf` — file type (`text`, `code`, `pdf`, `docx`, `pptx`, `image`, `other`)
+//! - `ext:md` — file extension
+//! - `path:finance/` — text the path (relative to the search directory) contains
+//! - `size:<5MB` — file size, with `<`, `<=`, `>`, `>=` or `=` and a `B`/`KB`/`MB`/`GB` unit
+//! - `modified:>2024-01-01` — modification date (UTC), with the same comparisons
+//!
+//! Values can be comma-separated lists. A file must pass every qualifier given;
+//! repeated `type:`, `ext:` and `path:` qualifiers accept any of their values,
+//! while repeated `size:` and `modified:` qualifiers narrow the range. A value that
+//! does not parse (`type:string`) leaves the token as text to search for, as does a
+//! leading backslash (`\type:pdf` searches for `type:pdf`).
+
+use crate::types::FileType;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds in a day.
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Comparison in a `size:` or `modified:` qualifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+impl Comparison {
+    /// Split a leading comparison operator off a value (`=` if there is none).
+    fn split(value: &str) -> (Self, &str) {
+        for (op, cmp) in [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ] {
+            if let Some(rest) = value.strip_prefix(op) {
+                return (cmp, rest);
+            }
+        }
+        (Comparison::Equal, value)
+    }
+
+    /// Whether `actual` compares to `expected` as required.
+    fn holds(self, actual: u64, expected: u64) -> bool {
+        match self {
+            Comparison::Less => actual < expected,
+            Comparison::LessOrEqual => actual <= expected,
+            Comparison::Greater => actual > expected,
+            Comparison::GreaterOrEqual => actual >= expected,
+            Comparison::Equal => actual == expected,
+        }
+    }
+}
+
+/// Filters on the files to search, from qualifiers in the pattern.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileFilters {
+    /// Accepted file types (empty = all).
+    types: Vec<FileType>,
+    /// Accepted extensions, lowercase without the dot (empty = all).
+    extensions: Vec<String>,
+    /// Text the relative path must contain, lowercase (any of them; empty = all).
+    paths: Vec<String>,
+    /// Size bounds in bytes.
+    sizes: Vec<(Comparison, u64)>,
+    /// Modification date bounds, in days since the Unix epoch.
+    modified: Vec<(Comparison, u64)>,
+}
+
+impl FileFilters {
+    /// Whether any qualifier was given.
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+            && self.extensions.is_empty()
+            && self.paths.is_empty()
+            && self.sizes.is_empty()
+            && self.modified.is_empty()
+    }
+
+    /// Whether the type, extension and path (relative to the search directory) pass.
+    pub fn accepts_path(&self, relative: &Path) -> bool {
+        let ext = relative
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        (self.types.is_empty() || self.types.contains(&FileType::from_extension(&ext)))
+            && (self.extensions.is_empty() || self.extensions.contains(&ext))
+            && (self.paths.is_empty() || {
+                let path = relative.to_string_lossy().replace('\\', "/").to_lowercase();
+                self.paths.iter().any(|p| path.contains(p.as_str()))
+            })
+    }
+
+    /// Whether the file's size and modification time pass.
+    pub fn accepts_metadata(&self, size: u64, modified: Option<SystemTime>) -> bool {
+        let day = modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() / SECS_PER_DAY);
+
+        self.sizes
+            .iter()
+            .all(|&(cmp, bound)| cmp.holds(size, bound))
+            && self
+                .modified
+                .iter()
+                .all(|&(cmp, bound)| day.is_some_and(|day| cmp.holds(day, bound)))
+    }
+
+    /// Whether the filters need file metadata (size or modification time).
+    pub fn needs_metadata(&self) -> bool {
+        !self.sizes.is_empty() || !self.modified.is_empty()
+    }
+
+    /// Add a `key:value` qualifier; returns false if the token is not a qualifier
+    /// or its value does not parse.
+    pub fn add_qualifier(&mut self, token: &str) -> bool {
+        token
+            .split_once(':')
+            .is_some_and(|(key, value)| !value.is_empty() && self.add(key, value))
+    }
+
+    /// Add a qualifier; returns false if the key is not a qualifier or the value
+    /// does not parse.
+    fn add(&mut self, key: &str, value: &str) -> bool {
+        let values = || value.split(',').filter(|v| !v.is_empty());
+
+        match key.to_lowercase().as_str() {
+            "type" => match values().map(parse_file_type).collect::<Option<Vec<_>>>() {
+                Some(types) => self.types.extend(types),
+                None => return false,
+            },
+            "ext" => self
+                .extensions
+                .extend(values().map(|v| v.trim_start_matches('.').to_lowercase())),
+            "path" => self.paths.extend(values().map(str::to_lowercase)),
+            "size" => {
+                let (cmp, size) = Comparison::split(value);
+                match parse_size(size) {
+                    Some(size) => self.sizes.push((cmp, size)),
+                    None => return false,
+                }
+            }
+            "modified" => {
+                let (cmp, date) = Comparison::split(value);
+                match parse_date(date) {
+                    Some(day) => self.modified.push((cmp, day)),
+                    None => return false,
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Split qualifiers off the pattern, returning the rest of the pattern and the filters.
+/// Qualifiers inside double quotes are left alone, and a backslash before one is
+/// removed and keeps it in the pattern.
+pub fn extract_qualifiers(pattern: &str) -> (String, FileFilters) {
+    let mut filters = FileFilters::default();
+    let mut rest = String::with_capacity(pattern.len());
+    let mut kept_until = 0;
+
+    for (start, end) in token_spans(pattern) {
+        let token = &pattern[start..end];
+        if let Some(escaped) = token.strip_prefix('\\') {
+            if is_qualifier(escaped) {
+                rest.push_str(&pattern[kept_until..start]);
+                kept_until = start + 1;
+            }
+            continue;
+        }
+        if !filters.add_qualifier(token) {
+            continue;
+        }
+
+        // Drop the qualifier and the whitespace after it, or before it at the end
+        rest.push_str(&pattern[kept_until..start]);
+        kept_until = pattern[end..]
+            .find(|c: char| !c.is_whitespace())
+            .map_or(pattern.len(), |pos| end + pos);
+        if kept_until == pattern.len() {
+            rest.truncate(rest.trim_end().len());
+        }
+    }
+    rest.push_str(&pattern[kept_until..]);
+
+    (rest, filters)
+}
+
+/// Whether a token would be taken as a qualifier.
+pub fn is_qualifier(token: &str) -> bool {
+    FileFilters::default().add_qualifier(token)
+}
+
+/// Byte spans of the whitespace-separated tokens of `pattern`, treating
+/// double-quoted text (with `\"` escapes) as part of one token.
+fn token_spans(pattern: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (pos, c) in pattern.char_indices() {
+        if c.is_whitespace() && !quoted {
+            if let Some(s) = start.take() {
+                spans.push((s, pos));
+            }
+            continue;
+        }
+        start.get_or_insert(pos);
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, pattern.len()));
+    }
+    spans
+}
+
+/// Parse a file type name (or an extension of that type).
+fn parse_file_type(name: &str) -> Option<FileType> {
+    match name.to_lowercase().as_str() {
+        "text" => Some(FileType::Text),
+        "code" => Some(FileType::Code),
+        "pdf" => Some(FileType::Pdf),
+        "docx" | "word" => Some(FileType::Docx),
+        "pptx" | "powerpoint" => Some(FileType::Pptx),
+        "image" => Some(FileType::Image),
+        "other" => Some(FileType::Other),
+        ext => match FileType::from_extension(ext) {
+            FileType::Other => None,
+            file_type => Some(file_type),
+        },
+    }
+}
+
+/// Parse a size such as `5MB`, `1.5GB` or `300` (bytes); units are powers of 1024.
+fn parse_size(size: &str) -> Option<u64> {
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier = match unit.to_uppercase().as_str() {
+        "" | "B" => 1u64,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Parse a `YYYY-MM-DD` date into days since the Unix epoch.
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days from civil date (proleptic Gregorian calendar)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    u64::try_from(era * 146_097 + day_of_era - 719_468).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_extract_qualifiers() {
+        let (rest, filters) = extract_qualifiers(
+            "invoice type:pdf path:Finance/ modified:>2024-01-01 size:<5MB  due",
+        );
+        assert_eq!(rest, "invoice due");
+        assert_eq!(filters.types, vec![FileType::Pdf]);
+        assert_eq!(filters.paths, vec!["finance/"]);
+        assert_eq!(filters.sizes, vec![(Comparison::Less, 5 << 20)]);
+        assert_eq!(filters.modified, vec![(Comparison::Greater, 19_723)]);
+
+        // Quoted text, unknown keys and URLs are part of the pattern
+        let (rest, filters) = extract_qualifiers(r#""type:pdf" note:x http://a ext:md,TXT"#);
+        assert_eq!(rest, r#""type:pdf" note:x http://a"#);
+        assert_eq!(filters.extensions, vec!["md", "txt"]);
+
+        // Whitespace is only removed next to a qualifier
+        assert_eq!(extract_qualifiers(" TODO ").0, " TODO ");
+        assert_eq!(extract_qualifiers(" TODO ext:md").0, " TODO");
+    }
+
+    #[test]
+    fn test_literal_qualifiers() {
+        // Values that don't parse are text to search for
+        for pattern in ["a size:big", "modified:2024-13-01", "type:string"] {
+            let (rest, filters) = extract_qualifiers(pattern);
+            assert_eq!(rest, pattern);
+            assert!(filters.is_empty());
+        }
+
+        let (rest, filters) = extract_qualifiers(r"\type:pdf \note:x ext:md");
+        assert_eq!(rest, r"type:pdf \note:x");
+        assert_eq!(filters.extensions, vec!["md"]);
+    }
+
+    #[test]
+    fn test_filters_accept() {
+        let (_, filters) =
+            extract_qualifiers("type:pdf,docx path:finance size:>=1KB modified:<=2024-01-01");
+        assert!(filters.accepts_path(Path::new("Finance/2023/q1.PDF")));
+        assert!(!filters.accepts_path(Path::new("finance/q1.txt")));
+        assert!(!filters.accepts_path(Path::new("hr/q1.pdf")));
+
+        let new_year = UNIX_EPOCH + Duration::from_secs(19_723 * SECS_PER_DAY + 3600);
+        assert!(filters.accepts_metadata(1024, Some(new_year)));
+        assert!(!filters.accepts_metadata(1023, Some(new_year)));
+        assert!(!filters.accepts_metadata(2048, Some(new_year + Duration::from_secs(SECS_PER_DAY))));
+        assert!(!filters.accepts_metadata(2048, None));
+    }
+}
@@ -0,0 +1,132 @@
+//! Case and accent folding for caseless matching.
+//!
+//! Folding can change the length of a string (`ẞ` folds to `ss`, `İ` to `i̇`),
+//! so folded text keeps the original offset of every byte and matches found in
+//! it are mapped back to spans of the original text.
+
+use caseless::Caseless;
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+/// How text is folded before comparing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Folding {
+    /// Apply Unicode default case folding.
+    pub case: bool,
+    /// Strip accents and other combining marks (`é` matches `e`).
+    pub accents: bool,
+}
+
+impl Folding {
+    /// Fold a string (e.g. the search pattern).
+    pub fn fold(self, text: &str) -> String {
+        let mut folded = String::with_capacity(text.len());
+        for c in text.chars() {
+            self.fold_char(c, |f| folded.push(f));
+        }
+        folded
+    }
+
+    /// Fold one character, passing the resulting characters (possibly none) to `emit`.
+    fn fold_char(self, c: char, mut emit: impl FnMut(char)) {
+        let mut push = |c: char| {
+            if self.accents {
+                decompose_canonical(c, |d| {
+                    if !is_combining_mark(d) {
+                        emit(d)
+                    }
+                });
+            } else {
+                emit(c);
+            }
+        };
+        if self.case {
+            std::iter::once(c).default_case_fold().for_each(&mut push);
+        } else {
+            push(c);
+        }
+    }
+}
+
+/// Text folded for comparison, with a map back to the original.
+pub struct FoldedText {
+    /// The folded text.
+    pub text: String,
+    /// Original byte offset of each folded byte, followed by the original length.
+    offsets: Vec<usize>,
+}
+
+impl FoldedText {
+    /// Fold `original` with the given folding.
+    pub fn new(original: &str, folding: Folding) -> Self {
+        let mut text = String::with_capacity(original.len());
+        let mut offsets = Vec::with_capacity(original.len() + 1);
+        for (pos, c) in original.char_indices() {
+            folding.fold_char(c, |f| {
+                text.push(f);
+                offsets.extend(std::iter::repeat_n(pos, f.len_utf8()));
+            });
+        }
+        offsets.push(original.len());
+        Self { text, offsets }
+    }
+
+    /// Map a byte span of the folded text back to a span of the original.
+    /// Returns `None` if the span starts or ends inside the folding of a single
+    /// character (e.g. just one `s` of the `ss` that `ß` folds to).
+    pub fn original_span(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        if start >= end {
+            return None;
+        }
+        let starts_char = start == 0 || self.offsets[start - 1] != self.offsets[start];
+        let ends_char = end == self.text.len() || self.offsets[end - 1] != self.offsets[end];
+        (starts_char && ends_char).then(|| (self.offsets[start], self.offsets[end]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASE: Folding = Folding {
+        case: true,
+        accents: false,
+    };
+
+    #[test]
+    fn test_case_folding_changes_lengths() {
+        assert_eq!(CASE.fold("Straẞe"), "strasse");
+        assert_eq!(CASE.fold("İstanbul"), "i\u{307}stanbul");
+
+        let folded = FoldedText::new("aẞb", CASE);
+        assert_eq!(folded.text, "assb");
+        // "ss" maps back onto the three bytes of ẞ
+        assert_eq!(folded.original_span(1, 3), Some((1, 4)));
+        assert_eq!(folded.original_span(3, 4), Some((4, 5)));
+        // Half of a folded character is not a match
+        assert_eq!(folded.original_span(1, 2), None);
+        // Empty spans are never a match
+        assert_eq!(folded.original_span(0, 0), None);
+    }
+
+    #[test]
+    fn test_accent_folding() {
+        let folding = Folding {
+            case: true,
+            accents: true,
+        };
+        assert_eq!(folding.fold("Résumé"), "resume");
+
+        // Decomposed input: the combining marks fold away with their letters
+        let original = "re\u{301}sume\u{301}!";
+        let folded = FoldedText::new(original, folding);
+        assert_eq!(folded.text, "resume!");
+        assert_eq!(folded.original_span(0, 6), Some((0, original.len() - 1)));
+    }
+
+    #[test]
+    fn test_identity_folding() {
+        let folded = FoldedText::new("Ünïcode", Folding::default());
+        assert_eq!(folded.text, "Ünïcode");
+        assert_eq!(folded.original_span(0, 2), Some((0, 2)));
+    }
+}
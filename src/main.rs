@@ -3,19 +3,45 @@
 //! A powerful CLI tool for searching text across any file format,
 //! including PDFs, Word documents, images (with OCR), and code files.
 
+mod dedupe;
 mod extractors;
+mod filters;
+mod fold;
 mod index;
+mod names;
+mod ocr_cache;
+#[cfg(feature = "ocr")]
+mod pdf_image;
+#[cfg(feature = "ocr")]
+mod pdf_ocr;
+mod query;
+mod rank;
+mod replace;
 mod search;
+mod stem;
+mod synonyms;
 mod types;
 mod ui;
 
 use clap::{Parser, ValueHint};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
 
+use ocr_cache::OcrCache;
 use search::SearchEngine;
-use types::{IndexConfig, OcrConfig, SearchConfig};
-use ui::{display_banner, display_error, display_results, flush, interactive_select, open_file};
+use synonyms::Synonyms;
+use dedupe::{find_duplicates, Fingerprint};
+use replace::{FileEdit, Replacer};
+use types::{
+    IndexConfig, MatchMode, OcrConfig, Ranking, RegexEngine, SearchConfig, SearchResult,
+    SearchTarget, Sort, SortKey,
+};
+use ui::{
+    display_banner, display_clusters, display_edit, display_edit_failure, display_error, display_extracted,
+    display_replace_summary, display_results, display_value_counts, flush, interactive_select,
+    open_file, ReplaceSummary,
+};
 
 /// Argus - The All-Seeing File Search Tool
 ///
@@ -32,18 +58,41 @@ use ui::{display_banner, display_error, display_results, flush, interactive_sele
                   argus \"TODO\"                    Search for TODO in current directory\n    \
                   argus -d ~/projects \"fn main\"   Search in specific directory\n    \
                   argus -r \"\\bfn\\s+\\w+\"           Use regex pattern matching\n    \
+                  argus -t foo -t bar             Search for several patterns at once\n    \
+                  argus -f watchlist.txt          Search for every pattern listed in a file\n    \
                   argus -e pdf,docx \"report\"      Search only in PDF and DOCX files\n    \
                   argus -o \"text in image\"        Enable OCR for images and scanned PDFs\n    \
                   argus -o -e pdf \"invoice\"       Search scanned PDF documents via OCR\n    \
                   argus -s -l 50 \"Error\"          Case-sensitive, limit to 50 results\n    \
+                  argus -L \"Copyright\"            List files missing a license header\n    \
+                  argus -N \"*.pdf\"                Find files by name (glob or fuzzy)\n    \
                   argus -i \"pattern\"              Save index for faster future searches\n    \
                   argus -I \"pattern\"              Use existing index if available\n    \
                   argus -iI \"pattern\"             Use index and update it with new files"
 )]
 struct Cli {
     /// The search pattern (text or regex with -r flag)
-    #[arg(required = true)]
-    pattern: String,
+    #[arg(required_unless_present_any = ["terms", "patterns_file", "dedupe"])]
+    pattern: Option<String>,
+
+    /// Also search for this pattern (repeatable); each match reports which pattern it hit
+    #[arg(
+        short = 't',
+        long = "term",
+        value_name = "PATTERN",
+        conflicts_with_all = ["query", "fuzzy"]
+    )]
+    terms: Vec<String>,
+
+    /// Read additional patterns from a file, one per line (blank lines are ignored)
+    #[arg(
+        short = 'f',
+        long = "patterns-file",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["query", "fuzzy"]
+    )]
+    patterns_file: Option<PathBuf>,
 
     /// Directory to search in
     #[arg(
@@ -54,6 +103,33 @@ struct Cli {
     )]
     directory: PathBuf,
 
+    /// Order results by match count, BM25 relevance, modification time or path
+    #[arg(
+        long = "rank",
+        value_name = "RANKING",
+        value_parser = ["count", "bm25", "recent", "path"],
+        default_value = "count"
+    )]
+    rank: String,
+
+    /// Sort results by path, modified, size, type or count, ascending
+    #[arg(
+        long = "sort",
+        value_name = "KEY",
+        value_parser = ["path", "modified", "size", "type", "count"],
+        conflicts_with_all = ["rank", "sortr"]
+    )]
+    sort: Option<String>,
+
+    /// Sort results by path, modified, size, type or count, descending
+    #[arg(
+        long = "sortr",
+        value_name = "KEY",
+        value_parser = ["path", "modified", "size", "type", "count"],
+        conflicts_with = "rank"
+    )]
+    sortr: Option<String>,
+
     /// Maximum number of results to display
     #[arg(short = 'l', long = "limit", default_value = "20")]
     limit: usize,
@@ -62,6 +138,10 @@ struct Cli {
     #[arg(short = 's', long = "case-sensitive")]
     case_sensitive: bool,
 
+    /// Ignore accents when matching fixed strings (e.g. `resume` finds `résumé`)
+    #[arg(short = 'a', long = "ignore-accents", conflicts_with = "regex")]
+    ignore_accents: bool,
+
     /// Enable OCR for images and scanned PDFs (requires Tesseract)
     #[arg(short = 'o', long = "ocr")]
     ocr: bool,
@@ -70,10 +150,144 @@ struct Cli {
     #[arg(short = 'r', long = "regex")]
     regex: bool,
 
+    /// Print only these capture groups of each regex match, by number or name (comma-separated)
+    #[arg(
+        long = "extract",
+        value_name = "GROUPS",
+        value_delimiter = ',',
+        requires = "regex",
+        conflicts_with_all = ["query", "terms", "patterns_file", "names", "with_names", "files_without_match"]
+    )]
+    extract: Option<Vec<String>>,
+
+    /// With --extract, count each distinct value across all files instead of listing matches
+    #[arg(long = "count-values", requires = "extract")]
+    count_values: bool,
+
+    /// Replace matches in text and code files with TEXT ($1 or ${name} insert regex groups); shows a diff unless --write
+    #[arg(
+        long = "replace",
+        value_name = "TEXT",
+        conflicts_with_all = [
+            "query", "fuzzy", "stem", "synonyms", "ignore_accents", "terms", "patterns_file",
+            "names", "with_names", "files_without_match", "extract"
+        ]
+    )]
+    replace: Option<String>,
+
+    /// With --replace, write the changes (keeping each original as FILE.bak)
+    #[arg(long = "write", requires = "replace")]
+    write: bool,
+
+    /// Find duplicate and near-duplicate documents instead of searching
+    #[arg(
+        long = "dedupe",
+        conflicts_with_all = [
+            "pattern", "terms", "patterns_file", "query", "regex", "names", "with_names",
+            "files_without_match", "extract", "replace"
+        ]
+    )]
+    dedupe: bool,
+
+    /// With --dedupe, the minimum text similarity of near-duplicates, in percent
+    #[arg(
+        long = "similarity",
+        value_name = "PERCENT",
+        default_value_t = 80,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        requires = "dedupe"
+    )]
+    similarity: u8,
+
+    /// Regex engine: `default` (fast), `fancy` (adds look-around and backreferences) or `pcre2`
+    #[arg(
+        long = "engine",
+        value_name = "ENGINE",
+        value_parser = ["default", "fancy", "pcre2"],
+        default_value = "default",
+        requires = "regex"
+    )]
+    engine: String,
+
+    /// Treat the pattern as a boolean query: AND, OR, NOT, (grouping) and "quoted phrases"
+    #[arg(short = 'q', long = "query")]
+    query: bool,
+
+    /// Filter files with type:, ext:, path:, size: and modified: in the pattern (always on with -q)
+    #[arg(long = "qualifiers", conflicts_with = "regex")]
+    qualifiers: bool,
+
+    /// Let regex matches span lines (match against the whole text)
+    #[arg(short = 'U', long = "multiline", requires = "regex")]
+    multiline: bool,
+
+    /// Only match whole words (e.g. `log` but not `catalog`)
+    #[arg(short = 'w', long = "word", conflicts_with = "line")]
+    word: bool,
+
+    /// Only match whole lines
+    #[arg(short = 'x', long = "line")]
+    line: bool,
+
+    /// Match words within N edits (Levenshtein distance) of the pattern
+    #[arg(long = "fuzzy", value_name = "N", conflicts_with = "regex")]
+    fuzzy: Option<usize>,
+
+    /// Match the pattern against file names and paths instead of contents (glob or fuzzy)
+    #[arg(
+        short = 'N',
+        long = "names",
+        conflicts_with_all = ["with_names", "regex", "query", "fuzzy", "terms", "patterns_file"]
+    )]
+    names: bool,
+
+    /// Match the pattern against file names and paths as well as contents
+    #[arg(
+        long = "with-names",
+        conflicts_with_all = ["regex", "query", "fuzzy", "terms", "patterns_file"]
+    )]
+    with_names: bool,
+
+    /// List the files that do NOT contain the pattern
+    #[arg(short = 'L', long = "files-without-match")]
+    files_without_match: bool,
+
+    /// Match words by their stems, e.g. `configure` finds `configured` (LANG defaults to english)
+    #[arg(
+        long = "stem",
+        value_name = "LANG",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "english",
+        conflicts_with_all = ["regex", "fuzzy", "terms", "patterns_file", "names", "with_names"]
+    )]
+    stem: Option<String>,
+
+    /// Also match the synonyms of each term listed in FILE (TOML, or JSON if it ends in .json)
+    #[arg(
+        long = "synonyms",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["regex", "fuzzy", "stem", "names", "with_names"]
+    )]
+    synonyms: Option<PathBuf>,
+
     /// Show content preview for each match
     #[arg(short = 'p', long = "preview")]
     preview: bool,
 
+    /// Show N lines of context after each match
+    #[arg(short = 'A', long = "after-context", value_name = "N")]
+    after_context: Option<usize>,
+
+    /// Show N lines of context before each match
+    #[arg(short = 'B', long = "before-context", value_name = "N")]
+    before_context: Option<usize>,
+
+    /// Show N lines of context before and after each match
+    #[arg(short = 'C', long = "context", value_name = "N")]
+    context: Option<usize>,
+
     /// Filter by file extensions (comma-separated, e.g., "pdf,txt,docx")
     #[arg(short = 'e', long = "extensions", value_delimiter = ',')]
     extensions: Option<Vec<String>>,
@@ -105,14 +319,38 @@ struct Cli {
     /// Path to index file (default: .argus_index.json in search directory)
     #[arg(long = "index-file", value_hint = ValueHint::FilePath)]
     index_file: Option<PathBuf>,
+
+    /// Give up extracting a single file after this many seconds (0 = no limit)
+    #[arg(long = "timeout", value_name = "SECS", default_value = "120")]
+    timeout: u64,
+
+    /// Stop starting new OCR work after this many seconds
+    #[arg(long = "ocr-budget", value_name = "SECS")]
+    ocr_budget: Option<u64>,
+
+    /// Maximum number of pages (images or scanned PDF pages) to OCR
+    #[arg(long = "ocr-max-pages", value_name = "N")]
+    ocr_max_pages: Option<usize>,
+
+    /// Directory for cached OCR output (default: argus/ocr in the user cache directory)
+    #[arg(long = "ocr-cache-dir", value_name = "DIR", value_hint = ValueHint::DirPath)]
+    ocr_cache_dir: Option<PathBuf>,
+
+    /// Maximum size of the OCR cache in megabytes
+    #[arg(long = "ocr-cache-size", value_name = "MB")]
+    ocr_cache_size: Option<u64>,
+
+    /// Don't read or write the OCR cache
+    #[arg(long = "no-ocr-cache", conflicts_with = "ocr_cache_dir")]
+    no_ocr_cache: bool,
 }
 
 fn main() {
     // Parse command line arguments
     let cli = Cli::parse();
 
-    // Display banner unless suppressed
-    if !cli.no_banner {
+    // Display banner unless suppressed; extracted values are meant for piping
+    if !cli.no_banner && cli.extract.is_none() {
         display_banner();
     }
 
@@ -141,21 +379,109 @@ fn main() {
         );
     }
 
+    // Gather additional patterns
+    let mut patterns = cli.terms;
+    if let Some(file) = &cli.patterns_file {
+        match read_patterns_file(file) {
+            Ok(from_file) => patterns.extend(from_file),
+            Err(e) => {
+                display_error(&format!(
+                    "Failed to read patterns file {}: {}",
+                    file.display(),
+                    e
+                ));
+                process::exit(1);
+            }
+        }
+    }
+    let pattern = cli.pattern.unwrap_or_default();
+    if pattern.is_empty() && patterns.is_empty() && !cli.dedupe {
+        display_error("No search patterns given");
+        process::exit(1);
+    }
+
+    let synonyms = cli.synonyms.as_deref().map(|file| match Synonyms::load(file) {
+        Ok(synonyms) => synonyms,
+        Err(e) => {
+            display_error(&format!(
+                "Failed to read synonyms file {}: {}",
+                file.display(),
+                e
+            ));
+            process::exit(1);
+        }
+    });
+
     // Build search configuration
     let directory = cli.directory.canonicalize().unwrap_or(cli.directory);
+    // Every extracted value is listed and every matching file edited, not just the first results
+    let limit = if (cli.extract.is_some() && !cli.count_values) || cli.replace.is_some() {
+        usize::MAX
+    } else {
+        cli.limit
+    };
     let config = SearchConfig {
         directory: directory.clone(),
-        pattern: cli.pattern,
+        pattern,
+        patterns,
         case_sensitive: cli.case_sensitive,
+        ignore_accents: cli.ignore_accents,
         use_regex: cli.regex,
+        extract: cli.extract.unwrap_or_default(),
+        regex_engine: match cli.engine.as_str() {
+            "fancy" => RegexEngine::Fancy,
+            "pcre2" => RegexEngine::Pcre2,
+            _ => RegexEngine::Standard,
+        },
+        use_query: cli.query,
+        qualifiers: cli.qualifiers,
+        // Backups of an earlier --write are not edited again
+        skip_backups: cli.replace.is_some(),
+        fuzzy: cli.fuzzy,
+        stem_language: cli.stem,
+        synonyms,
+        match_mode: if cli.word {
+            MatchMode::Word
+        } else if cli.line {
+            MatchMode::Line
+        } else {
+            MatchMode::Substring
+        },
+        multiline: cli.multiline,
+        files_without_match: cli.files_without_match,
+        target: if cli.names {
+            SearchTarget::Names
+        } else if cli.with_names {
+            SearchTarget::NamesAndContent
+        } else {
+            SearchTarget::Content
+        },
         ocr: OcrConfig {
             enabled: cli.ocr,
+            max_pages: cli.ocr_max_pages,
+            time_budget: cli.ocr_budget.map(Duration::from_secs),
+            cache_dir: if cli.no_ocr_cache {
+                None
+            } else {
+                cli.ocr_cache_dir.or_else(OcrCache::default_dir)
+            },
+            cache_size: cli.ocr_cache_size.map(|mb| mb * 1024 * 1024),
+        },
+        ranking: match cli.rank.as_str() {
+            "bm25" => Ranking::Bm25,
+            "recent" => Ranking::Recent,
+            "path" => Ranking::Path,
+            _ => Ranking::Count,
         },
-        limit: cli.limit,
+        sort: sort_order(cli.sort.as_deref(), cli.sortr.as_deref()),
+        limit,
         max_depth: cli.max_depth,
         include_hidden: cli.hidden,
         extensions: cli.extensions.unwrap_or_default(),
         show_preview: cli.preview,
+        file_timeout: (cli.timeout > 0).then(|| Duration::from_secs(cli.timeout)),
+        context_before: cli.before_context.or(cli.context).unwrap_or(0),
+        context_after: cli.after_context.or(cli.context).unwrap_or(0),
     };
 
     // Build index configuration
@@ -169,16 +495,63 @@ fn main() {
     let mut engine = match SearchEngine::new(config.clone(), index_config) {
         Ok(e) => e,
         Err(e) => {
-            display_error(&format!("Invalid regex pattern: {}", e));
+            display_error(&e.to_string());
             process::exit(1);
         }
     };
 
+    // Dedupe mode compares the files' contents instead of searching them
+    if cli.dedupe {
+        let (fingerprints, stats) = engine.map_texts(|path, file_type, text| {
+            Fingerprint::new(path, file_type, text.unwrap_or_default())
+        });
+        let clusters = find_duplicates(fingerprints, f64::from(cli.similarity) / 100.0);
+        display_clusters(&clusters, &stats);
+        flush();
+        #[cfg(feature = "ocr")]
+        suppress_stderr();
+        return;
+    }
+
     // Execute search
     let (results, stats) = engine.search();
 
+    // Replacements are shown as a diff, or written, instead of listing results
+    if let Some(replacement) = &cli.replace {
+        match engine.replacer(replacement) {
+            Ok(replacer) => replace_in_results(&results, &replacer, cli.write),
+            Err(e) => {
+                display_error(&e.to_string());
+                process::exit(1);
+            }
+        }
+        flush();
+        #[cfg(feature = "ocr")]
+        suppress_stderr();
+        return;
+    }
+
+    // Extracted values are printed plainly, for piping into other tools
+    if !config.extract.is_empty() {
+        if cli.count_values {
+            display_value_counts(&stats);
+        } else {
+            display_extracted(&results, &config.extract);
+        }
+        flush();
+        #[cfg(feature = "ocr")]
+        suppress_stderr();
+        return;
+    }
+
     // Display results
-    display_results(&results, &stats, config.show_preview);
+    display_results(
+        &results,
+        &stats,
+        config.show_preview,
+        config.match_mode,
+        config.files_without_match,
+    );
     flush();
 
     // Skip interactive mode if non-interactive flag is set
@@ -210,6 +583,67 @@ fn main() {
     suppress_stderr();
 }
 
+/// Plan the replacements in each result, in path order, and show them as a diff
+/// or write them.
+fn replace_in_results(results: &[SearchResult], replacer: &Replacer, write: bool) {
+    let mut results: Vec<&SearchResult> = results.iter().collect();
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut summary = ReplaceSummary {
+        write,
+        ..Default::default()
+    };
+    for result in results {
+        let planned = FileEdit::plan(&result.path, result.file_type, replacer);
+        let edit = match planned {
+            Ok(Some(edit)) => edit,
+            Ok(None) => continue,
+            Err(e) => {
+                display_edit_failure(&result.path, &e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        if write {
+            if let Err(e) = edit.write() {
+                display_edit_failure(&result.path, &e);
+                summary.failed += 1;
+                continue;
+            }
+        }
+        display_edit(&edit);
+        summary.files += 1;
+        summary.replacements += edit.replacements;
+    }
+    display_replace_summary(&summary);
+}
+
+/// The sort requested with `--sort` (ascending) or `--sortr` (descending).
+fn sort_order(ascending: Option<&str>, descending: Option<&str>) -> Option<Sort> {
+    let (key, descending) = match (ascending, descending) {
+        (Some(key), _) => (key, false),
+        (None, Some(key)) => (key, true),
+        (None, None) => return None,
+    };
+    let key = match key {
+        "modified" => SortKey::Modified,
+        "size" => SortKey::Size,
+        "type" => SortKey::Type,
+        "count" => SortKey::Count,
+        _ => SortKey::Path,
+    };
+    Some(Sort { key, descending })
+}
+
+/// Read search patterns from a file, one per line, skipping blank lines.
+fn read_patterns_file(path: &Path) -> std::io::Result<Vec<String>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
 /// Redirect stderr to /dev/null to suppress third-party library warnings at exit.
 #[cfg(feature = "ocr")]
 fn suppress_stderr() {
@@ -0,0 +1,145 @@
+//! Matching the search pattern against file names and paths.
+//!
+//! A pattern containing glob metacharacters (`*`, `?`, `[`, `{`) is a glob: it is
+//! matched against the file name, or against the path relative to the search
+//! directory if it contains a `/`. Any other pattern is matched fuzzily: its
+//! characters must appear in order in the relative path, and hits in the file
+//! name, at word starts and in consecutive runs score higher.
+
+use crate::fold::Folding;
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::Path;
+
+/// Characters that make a pattern a glob.
+const GLOB_CHARS: &[char] = &['*', '?', '[', '{'];
+
+/// A compiled file name pattern.
+pub enum NameMatcher {
+    /// Glob, matched against the whole relative path or just the file name.
+    Glob { glob: GlobMatcher, full_path: bool },
+    /// Folded pattern characters, matched in order.
+    Fuzzy { chars: Vec<char>, folding: Folding },
+}
+
+impl NameMatcher {
+    /// Compile a name pattern.
+    pub fn new(pattern: &str, folding: Folding) -> Result<Self, globset::Error> {
+        if pattern.contains(GLOB_CHARS) {
+            let glob = GlobBuilder::new(pattern)
+                .case_insensitive(folding.case)
+                .literal_separator(true)
+                .build()?
+                .compile_matcher();
+            Ok(NameMatcher::Glob {
+                glob,
+                full_path: pattern.contains('/'),
+            })
+        } else {
+            Ok(NameMatcher::Fuzzy {
+                chars: folding.fold(pattern).chars().collect(),
+                folding,
+            })
+        }
+    }
+
+    /// Score a path relative to the search directory: `None` if it does not match,
+    /// otherwise a score between 0.0 and 1.0 (globs always score 1.0).
+    pub fn score(&self, relative: &Path) -> Option<f64> {
+        match self {
+            NameMatcher::Glob { glob, full_path } => {
+                let matched = if *full_path {
+                    glob.is_match(relative)
+                } else {
+                    relative.file_name().is_some_and(|name| glob.is_match(name))
+                };
+                matched.then_some(1.0)
+            }
+            NameMatcher::Fuzzy { chars, folding } => {
+                let path = folding.fold(&relative.to_string_lossy().replace('\\', "/"));
+                let name_start = path.rfind('/').map_or(0, |pos| pos + 1);
+
+                // Prefer a match within the file name to one spread over directories
+                if let Some(score) = fuzzy_score(chars, &path[name_start..]) {
+                    Some(0.5 + score / 2.0)
+                } else {
+                    fuzzy_score(chars, &path).map(|score| score / 2.0)
+                }
+            }
+        }
+    }
+}
+
+/// Score `pattern` as a subsequence of `candidate`, between 0.0 and 1.0.
+/// Each matched character earns a point, plus one if it follows the previous match
+/// directly and one if it starts a word, so a contiguous hit at a word start scores 1.0.
+fn fuzzy_score(pattern: &[char], candidate: &str) -> Option<f64> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let mut points = 0;
+    let mut wanted = pattern.iter().peekable();
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+
+    for c in candidate.chars() {
+        let matched = wanted.next_if(|&&w| w == c).is_some();
+        if matched {
+            points += 1;
+            if previous_matched {
+                points += 1;
+            }
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                points += 1;
+            }
+        }
+        previous_matched = matched;
+        previous = Some(c);
+    }
+
+    wanted
+        .peek()
+        .is_none()
+        .then(|| (points as f64 / (2 * pattern.len()) as f64).min(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: &str) -> NameMatcher {
+        let folding = Folding {
+            case: true,
+            accents: false,
+        };
+        NameMatcher::new(pattern, folding).unwrap()
+    }
+
+    #[test]
+    fn test_glob_names_and_paths() {
+        let name = matcher("*.PDF");
+        assert_eq!(name.score(Path::new("docs/2023/report.pdf")), Some(1.0));
+        assert_eq!(name.score(Path::new("docs/report.txt")), None);
+
+        // A glob with a separator is matched against the whole relative path
+        let path = matcher("docs/*/report.*");
+        assert_eq!(path.score(Path::new("docs/2023/report.pdf")), Some(1.0));
+        assert_eq!(path.score(Path::new("docs/2023/q1/report.pdf")), None);
+    }
+
+    #[test]
+    fn test_fuzzy_names() {
+        let fuzzy = matcher("invrep");
+        let in_name = fuzzy.score(Path::new("2023/invoice_report.pdf")).unwrap();
+        let spread = fuzzy.score(Path::new("invoices/reports.txt")).unwrap();
+        assert!(in_name > 0.5 && spread <= 0.5);
+        assert_eq!(fuzzy.score(Path::new("invoice.pdf")), None);
+
+        // A contiguous hit at a word start outranks a scattered one
+        let exact = matcher("report");
+        let contiguous = exact.score(Path::new("report.pdf")).unwrap();
+        let scattered = exact.score(Path::new("rxexpxoxrxt.pdf")).unwrap();
+        assert!(contiguous > scattered);
+        assert_eq!(contiguous, 1.0);
+    }
+}
@@ -0,0 +1,231 @@
+//! Content-addressed cache for OCR output.
+//!
+//! OCR is by far the most expensive extraction step, so its output is cached
+//! separately from the per-directory index, in the user's cache directory.
+//! Entries are keyed by a hash of the image bytes handed to Tesseract (or, for a
+//! scanned PDF page, of the PDF's bytes and the page number) plus the OCR
+//! settings, so a copied, renamed or moved file is never OCR'd twice, and
+//! every search and index on the machine shares the same cache. The cache is
+//! kept under a size limit by evicting the least recently used entries.
+
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Cache format version. Increment to invalidate all existing entries.
+const CACHE_VERSION: u32 = 1;
+
+/// Default maximum cache size (512 MB).
+pub const DEFAULT_CACHE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Extension used for cache entry files.
+const ENTRY_EXTENSION: &str = "txt";
+
+/// Distinguishes temporary files written concurrently by one process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A directory of cached OCR results.
+#[derive(Debug)]
+pub struct OcrCache {
+    /// Root directory of the cache.
+    dir: PathBuf,
+    /// Size the cache is trimmed to by [`OcrCache::evict`].
+    max_bytes: u64,
+    /// OCR settings mixed into every key (engine, language, ...).
+    settings: String,
+}
+
+impl OcrCache {
+    /// Open (creating if needed) a cache rooted at `dir`.
+    pub fn open(dir: PathBuf, max_bytes: u64, settings: &str) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            max_bytes,
+            settings: settings.to_string(),
+        })
+    }
+
+    /// The default cache location: `argus/ocr` inside the user cache directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|d| d.join("argus").join("ocr"))
+    }
+
+    /// Compute the cache key for an image's bytes under the current OCR settings.
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    pub fn key(&self, content: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_VERSION.to_le_bytes());
+        hasher.update((self.settings.len() as u64).to_le_bytes());
+        hasher.update(self.settings.as_bytes());
+        hasher.update(content);
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Look up cached OCR text, marking the entry as recently used.
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.entry_path(key);
+        let text = fs::read_to_string(&path).ok()?;
+
+        // Refresh the modification time so eviction sees the entry as recently used
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some(text)
+    }
+
+    /// Store OCR text under a key.
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    pub fn put(&self, key: &str, text: &str) -> io::Result<()> {
+        let path = self.entry_path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file and rename so concurrent readers never see partial entries
+        let temp_path = path.with_extension(format!(
+            "{}.{}",
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = File::create(&temp_path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .and_then(|_| fs::rename(&temp_path, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        written
+    }
+
+    /// Evict least recently used entries until the cache fits its size limit.
+    /// Returns the number of entries removed.
+    pub fn evict(&self) -> usize {
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|e| e.size).sum();
+        if total <= self.max_bytes {
+            return 0;
+        }
+
+        entries.sort_by_key(|e| e.modified);
+        let mut removed = 0;
+        for entry in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&entry.path).is_ok() {
+                total = total.saturating_sub(entry.size);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Path of the file holding an entry, sharded by the first two hex digits.
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let shard = key.get(..2).unwrap_or("00");
+        self.dir
+            .join(shard)
+            .join(format!("{}.{}", key, ENTRY_EXTENSION))
+    }
+
+    /// List every entry in the cache.
+    fn entries(&self) -> Vec<CacheEntry> {
+        let mut entries = Vec::new();
+        let shards = match fs::read_dir(&self.dir) {
+            Ok(s) => s,
+            Err(_) => return entries,
+        };
+
+        for shard in shards.filter_map(|s| s.ok()) {
+            let files = match fs::read_dir(shard.path()) {
+                Ok(f) => f,
+                Err(_) => continue,
+            };
+            for file in files.filter_map(|f| f.ok()) {
+                let path = file.path();
+                if path.extension().and_then(|e| e.to_str()) != Some(ENTRY_EXTENSION) {
+                    continue;
+                }
+                if let Ok(metadata) = file.metadata() {
+                    entries.push(CacheEntry {
+                        size: metadata.len(),
+                        modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                        path,
+                    });
+                }
+            }
+        }
+        entries
+    }
+}
+
+/// A cache entry on disk.
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_put_and_get() {
+        let dir = tempdir().unwrap();
+        let cache = OcrCache::open(dir.path().to_path_buf(), DEFAULT_CACHE_SIZE, "eng").unwrap();
+
+        let key = cache.key(b"image bytes");
+        assert!(cache.get(&key).is_none());
+
+        cache.put(&key, "recognised text").unwrap();
+        assert_eq!(cache.get(&key).as_deref(), Some("recognised text"));
+    }
+
+    #[test]
+    fn test_key_depends_on_content_and_settings() {
+        let dir = tempdir().unwrap();
+        let eng = OcrCache::open(dir.path().to_path_buf(), DEFAULT_CACHE_SIZE, "eng").unwrap();
+        let deu = OcrCache::open(dir.path().to_path_buf(), DEFAULT_CACHE_SIZE, "deu").unwrap();
+
+        assert_eq!(eng.key(b"same"), eng.key(b"same"));
+        assert_ne!(eng.key(b"same"), eng.key(b"other"));
+        assert_ne!(eng.key(b"same"), deu.key(b"same"));
+    }
+
+    #[test]
+    fn test_evict_removes_least_recently_used() {
+        let dir = tempdir().unwrap();
+        let cache = OcrCache::open(dir.path().to_path_buf(), 10, "eng").unwrap();
+
+        let old = cache.key(b"old");
+        let new = cache.key(b"new");
+        cache.put(&old, "123456").unwrap();
+        cache.put(&new, "abcdef").unwrap();
+
+        // Make the first entry clearly older
+        let old_path = cache.entry_path(&old);
+        File::options()
+            .write(true)
+            .open(&old_path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+
+        assert_eq!(cache.evict(), 1);
+        assert!(cache.get(&old).is_none());
+        assert!(cache.get(&new).is_some());
+    }
+}
@@ -0,0 +1,732 @@
+//! Decoding of PDF image XObjects into pixels for OCR.
+//!
+//! Decoding happens in two steps. The stream's filters are applied first,
+//! which yields either a finished image (JPEG) or raw samples packed at
+//! `BitsPerComponent` bits. Raw samples are then interpreted through the
+//! image's colour space and `Decode` array to produce grayscale or RGB pixels.
+
+use image::{DynamicImage, GrayImage, ImageFormat, Luma, Rgb, RgbImage};
+use lopdf::{Dictionary, Document, Object, Stream};
+use std::io::Read;
+use std::process::Command;
+
+/// Largest image (in pixels) we are willing to decode.
+const MAX_PIXELS: u64 = 100_000_000;
+
+/// Result of running an image stream through its filters.
+enum Filtered {
+    /// Raw samples, `BitsPerComponent` bits each, rows padded to a byte boundary.
+    Samples(Vec<u8>),
+    /// A complete image produced by a format decoder (DCTDecode).
+    Image(DynamicImage),
+}
+
+/// The colour spaces we can turn into pixels.
+#[derive(Debug, Clone, PartialEq)]
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// A single tint component (Separation, or one-ink DeviceN); 1.0 is full ink.
+    Tint,
+    /// A palette: `base` colour space and `lookup` table of base-space samples.
+    Indexed {
+        base: Box<ColorSpace>,
+        hival: u32,
+        lookup: Vec<u8>,
+    },
+}
+
+impl ColorSpace {
+    /// Number of components per sample in this colour space.
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Tint | ColorSpace::Indexed { .. } => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+}
+
+/// Get the list of filters applied to a PDF stream.
+pub fn get_stream_filters(dict: &Dictionary) -> Vec<Vec<u8>> {
+    match dict.get(b"Filter") {
+        Ok(Object::Name(n)) => vec![n.clone()],
+        Ok(Object::Array(arr)) => arr
+            .iter()
+            .filter_map(|o| {
+                if let Object::Name(n) = o {
+                    Some(n.clone())
+                } else {
+                    None
+                }
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Decode an image XObject into pixels.
+/// Returns None if the image format is unsupported or decoding fails.
+pub fn decode_image(doc: &Document, stream: &Stream) -> Option<DynamicImage> {
+    let dict = &stream.dict;
+    let width = positive_integer(dict.get(b"Width").ok()?)?;
+    let height = positive_integer(dict.get(b"Height").ok()?)?;
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return None;
+    }
+
+    let samples = match apply_filters(doc, stream, width, height)? {
+        Filtered::Image(image) => return Some(image),
+        Filtered::Samples(samples) => samples,
+    };
+
+    // Stencil masks are 1-bit images painted in the fill colour (black for scans)
+    let is_mask = matches!(dict.get(b"ImageMask"), Ok(Object::Boolean(true)));
+    if is_mask {
+        return decode_stencil_mask(&samples, width, height, decode_array(doc, dict));
+    }
+
+    let bpc = match dict
+        .get(b"BitsPerComponent")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+    {
+        Some((_, Object::Integer(b))) => *b as u32,
+        _ => 8,
+    };
+    if ![1, 2, 4, 8, 16].contains(&bpc) {
+        return None;
+    }
+
+    let color_space = dict
+        .get(b"ColorSpace")
+        .ok()
+        .and_then(|o| resolve_color_space(doc, o, 0))
+        .unwrap_or(ColorSpace::Rgb);
+
+    samples_to_image(
+        &samples,
+        width,
+        height,
+        bpc,
+        &color_space,
+        decode_array(doc, dict).as_deref(),
+    )
+}
+
+/// Run the stream content through its filter chain.
+fn apply_filters(doc: &Document, stream: &Stream, width: u32, height: u32) -> Option<Filtered> {
+    let filters = get_stream_filters(&stream.dict);
+    let params = decode_params(doc, &stream.dict, filters.len());
+
+    let mut data = stream.content.clone();
+    for (filter, params) in filters.iter().zip(params.iter()) {
+        data = match filter.as_slice() {
+            b"FlateDecode" | b"Fl" => {
+                let mut inflated = Vec::new();
+                // Truncated streams are common; keep whatever inflated cleanly
+                let _ = flate2::read::ZlibDecoder::new(data.as_slice()).read_to_end(&mut inflated);
+                if inflated.is_empty() {
+                    return None;
+                }
+                apply_predictor(inflated, params.as_ref())?
+            }
+            b"DCTDecode" | b"DCT" => {
+                let image = image::load_from_memory_with_format(&data, ImageFormat::Jpeg).ok()?;
+                return Some(Filtered::Image(image));
+            }
+            b"CCITTFaxDecode" | b"CCF" => decode_ccitt(&data, width, height, params.as_ref())?,
+            b"JBIG2Decode" => decode_jbig2(doc, &data, params.as_ref())?,
+            _ => return None, // Unsupported filter (JPX, LZW, etc.)
+        };
+    }
+
+    Some(Filtered::Samples(data))
+}
+
+/// Collect the `DecodeParms` dictionary for each filter, resolving references.
+fn decode_params(doc: &Document, dict: &Dictionary, count: usize) -> Vec<Option<Dictionary>> {
+    let as_dict = |o: &Object| match doc.dereference(o) {
+        Ok((_, Object::Dictionary(d))) => Some(d.clone()),
+        _ => None,
+    };
+
+    let mut params = match dict
+        .get(b"DecodeParms")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+    {
+        Some((_, Object::Array(arr))) => arr.iter().map(as_dict).collect(),
+        Some((_, o)) => vec![as_dict(o)],
+        None => Vec::new(),
+    };
+    params.resize(count, None);
+    params
+}
+
+/// Read an integer entry from a `DecodeParms` dictionary.
+fn param_int(params: Option<&Dictionary>, key: &[u8], default: i64) -> i64 {
+    params
+        .and_then(|p| p.get(key).ok())
+        .and_then(|o| o.as_i64().ok())
+        .unwrap_or(default)
+}
+
+/// Read a boolean entry from a `DecodeParms` dictionary.
+fn param_bool(params: Option<&Dictionary>, key: &[u8]) -> bool {
+    params
+        .and_then(|p| p.get(key).ok())
+        .and_then(|o| o.as_bool().ok())
+        .unwrap_or(false)
+}
+
+/// Undo a PNG (10-15) or TIFF (2) predictor applied before Flate compression.
+fn apply_predictor(data: Vec<u8>, params: Option<&Dictionary>) -> Option<Vec<u8>> {
+    let predictor = param_int(params, b"Predictor", 1);
+    if predictor < 2 {
+        return Some(data);
+    }
+
+    let colors = param_int(params, b"Colors", 1).max(1) as usize;
+    let bpc = param_int(params, b"BitsPerComponent", 8).max(1) as usize;
+    let columns = param_int(params, b"Columns", 1).max(1) as usize;
+    let row_len = (colors * bpc * columns).div_ceil(8);
+    let bytes_per_pixel = (colors * bpc).div_ceil(8);
+
+    if predictor == 2 {
+        // TIFF horizontal differencing; only byte-aligned samples are worth supporting
+        if bpc != 8 {
+            return None;
+        }
+        let mut out = data;
+        for row in out.chunks_mut(row_len) {
+            for i in bytes_per_pixel..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bytes_per_pixel]);
+            }
+        }
+        return Some(out);
+    }
+
+    // PNG predictors: every row starts with its own filter-type byte
+    let mut out = Vec::with_capacity(data.len());
+    let mut prev = vec![0u8; row_len];
+    for chunk in data.chunks(row_len + 1) {
+        if chunk.len() < 2 {
+            break;
+        }
+        let filter_type = chunk[0];
+        let mut row = chunk[1..].to_vec();
+        row.resize(row_len, 0);
+
+        for i in 0..row_len {
+            let left = if i >= bytes_per_pixel {
+                row[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up = prev[i];
+            let up_left = if i >= bytes_per_pixel {
+                prev[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            row[i] = match filter_type {
+                0 => row[i],
+                1 => row[i].wrapping_add(left),
+                2 => row[i].wrapping_add(up),
+                3 => row[i].wrapping_add(((left as u16 + up as u16) / 2) as u8),
+                4 => row[i].wrapping_add(paeth(left, up, up_left)),
+                _ => return None,
+            };
+        }
+        out.extend_from_slice(&row);
+        prev = row;
+    }
+    Some(out)
+}
+
+/// The PNG Paeth predictor.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Decode CCITT Group 3 (1-D) or Group 4 fax data into 1-bit samples.
+fn decode_ccitt(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    params: Option<&Dictionary>,
+) -> Option<Vec<u8>> {
+    let k = param_int(params, b"K", 0);
+    // The fax decoder counts in u16; larger images can't be decoded
+    let columns = u16::try_from(param_int(params, b"Columns", 1728).max(1)).ok()?;
+    let rows = u16::try_from(param_int(params, b"Rows", height as i64).max(0)).ok()?;
+    let black_is_1 = param_bool(params, b"BlackIs1");
+
+    // The filter outputs 0 for black unless BlackIs1 is set
+    let (black_bit, white_bit) = if black_is_1 { (1u8, 0u8) } else { (0u8, 1u8) };
+    let row_bytes = (columns as usize).div_ceil(8);
+    let mut out = Vec::with_capacity(row_bytes * rows as usize);
+
+    let mut push_line = |transitions: &[u16]| {
+        let mut row = vec![0u8; row_bytes];
+        for (x, color) in fax::decoder::pels(transitions, columns).enumerate() {
+            let bit = if color == fax::Color::Black {
+                black_bit
+            } else {
+                white_bit
+            };
+            row[x / 8] |= bit << (7 - (x % 8));
+        }
+        out.extend_from_slice(&row);
+    };
+
+    let rows_hint = if rows > 0 { Some(rows) } else { None };
+    match k {
+        k if k < 0 => {
+            fax::decoder::decode_g4(data.iter().copied(), columns, rows_hint, &mut push_line)?;
+        }
+        0 => {
+            // Pure 1-D coding; a missing trailing EOL still leaves usable rows
+            let _ = fax::decoder::decode_g3(data.iter().copied(), &mut push_line);
+        }
+        _ => return None, // Mixed 1-D/2-D coding is not supported
+    }
+
+    // Pad short images with white so the sample buffer matches the declared size
+    let expected = (width as usize).div_ceil(8) * height as usize;
+    if out.is_empty() || columns as u32 != width {
+        return None;
+    }
+    out.resize(expected, if white_bit == 1 { 0xFF } else { 0x00 });
+    Some(out)
+}
+
+/// Decode an embedded JBIG2 stream into 1-bit samples using the `jbig2dec` tool.
+fn decode_jbig2(doc: &Document, data: &[u8], params: Option<&Dictionary>) -> Option<Vec<u8>> {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().ok()?;
+    let page_path = dir.path().join("page.jb2");
+    let out_path = dir.path().join("page.pbm");
+    std::fs::File::create(&page_path)
+        .ok()?
+        .write_all(data)
+        .ok()?;
+
+    let mut command = Command::new("jbig2dec");
+    command
+        .arg("--embedded")
+        .arg("--format")
+        .arg("pbm")
+        .arg("--output")
+        .arg(&out_path);
+
+    // Shared symbol dictionaries live in a separate JBIG2Globals stream
+    let globals = params
+        .and_then(|p| p.get(b"JBIG2Globals").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_stream().ok());
+    if let Some(globals) = globals {
+        let globals_path = dir.path().join("globals.jb2");
+        let content = globals
+            .decompressed_content()
+            .unwrap_or_else(|_| globals.content.clone());
+        std::fs::File::create(&globals_path)
+            .ok()?
+            .write_all(&content)
+            .ok()?;
+        command.arg(&globals_path);
+    }
+    command.arg(&page_path);
+
+    let status = command.output().ok()?.status;
+    if !status.success() {
+        return None;
+    }
+
+    // PBM decodes to black = 0, which is also what the JBIG2Decode filter produces
+    let decoded = image::open(&out_path).ok()?.to_luma8();
+    let (w, h) = decoded.dimensions();
+    let row_bytes = (w as usize).div_ceil(8);
+    let mut out = vec![0u8; row_bytes * h as usize];
+    for (x, y, pixel) in decoded.enumerate_pixels() {
+        if pixel[0] >= 128 {
+            out[y as usize * row_bytes + x as usize / 8] |= 1 << (7 - (x % 8));
+        }
+    }
+    Some(out)
+}
+
+/// Resolve a colour space object into something we can convert.
+fn resolve_color_space(doc: &Document, object: &Object, depth: usize) -> Option<ColorSpace> {
+    if depth > 4 {
+        return None;
+    }
+    let (_, object) = doc.dereference(object).ok()?;
+
+    match object {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" | b"CalGray" | b"G" => Some(ColorSpace::Gray),
+            b"DeviceRGB" | b"CalRGB" | b"RGB" => Some(ColorSpace::Rgb),
+            b"DeviceCMYK" | b"CMYK" => Some(ColorSpace::Cmyk),
+            _ => None,
+        },
+        Object::Array(arr) => {
+            let family = arr.first()?.as_name().ok()?;
+            match family {
+                b"CalGray" => Some(ColorSpace::Gray),
+                b"CalRGB" | b"Lab" => Some(ColorSpace::Rgb),
+                b"ICCBased" => {
+                    let (_, profile) = doc.dereference(arr.get(1)?).ok()?;
+                    let profile = profile.as_stream().ok()?;
+                    if let Ok(alternate) = profile.dict.get(b"Alternate") {
+                        if let Some(cs) = resolve_color_space(doc, alternate, depth + 1) {
+                            return Some(cs);
+                        }
+                    }
+                    match profile.dict.get(b"N").and_then(Object::as_i64) {
+                        Ok(1) => Some(ColorSpace::Gray),
+                        Ok(4) => Some(ColorSpace::Cmyk),
+                        _ => Some(ColorSpace::Rgb),
+                    }
+                }
+                b"Indexed" | b"I" => {
+                    let base = resolve_color_space(doc, arr.get(1)?, depth + 1)?;
+                    let (_, hival) = doc.dereference(arr.get(2)?).ok()?;
+                    let hival = hival.as_i64().ok()?.clamp(0, 255) as u32;
+                    let lookup = match doc.dereference(arr.get(3)?).ok()?.1 {
+                        Object::String(bytes, _) => bytes.clone(),
+                        Object::Stream(s) => s
+                            .decompressed_content()
+                            .unwrap_or_else(|_| s.content.clone()),
+                        _ => return None,
+                    };
+                    Some(ColorSpace::Indexed {
+                        base: Box::new(base),
+                        hival,
+                        lookup,
+                    })
+                }
+                b"Separation" => Some(ColorSpace::Tint),
+                b"DeviceN" => match doc.dereference(arr.get(1)?).ok()?.1 {
+                    Object::Array(names) if names.len() == 1 => Some(ColorSpace::Tint),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Read the `Decode` array as a list of numbers.
+fn decode_array(doc: &Document, dict: &Dictionary) -> Option<Vec<f32>> {
+    match dict
+        .get(b"Decode")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+    {
+        Some((_, Object::Array(arr))) => {
+            Some(arr.iter().filter_map(|o| o.as_float().ok()).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Decode a 1-bit stencil mask into a black-on-white grayscale image.
+fn decode_stencil_mask(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    decode: Option<Vec<f32>>,
+) -> Option<DynamicImage> {
+    let row_bytes = (width as usize).div_ceil(8);
+    if data.len() < row_bytes * height as usize {
+        return None;
+    }
+
+    // With the default Decode [0 1], a 0 sample paints and a 1 sample leaves the page untouched
+    let paint_bit = match decode.as_deref() {
+        Some([first, ..]) if *first == 1.0 => 1,
+        _ => 0,
+    };
+
+    let gray = GrayImage::from_fn(width, height, |x, y| {
+        let byte = data[y as usize * row_bytes + (x as usize / 8)];
+        let bit = (byte >> (7 - (x % 8))) & 1;
+        if bit == paint_bit {
+            Luma([0])
+        } else {
+            Luma([255])
+        }
+    });
+    Some(DynamicImage::ImageLuma8(gray))
+}
+
+/// Convert packed samples into pixels through a colour space and Decode array.
+fn samples_to_image(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    bpc: u32,
+    color_space: &ColorSpace,
+    decode: Option<&[f32]>,
+) -> Option<DynamicImage> {
+    let components = color_space.components();
+    let row_bits = width as usize * components * bpc as usize;
+    let row_bytes = row_bits.div_ceil(8);
+    if data.len() < row_bytes * height as usize {
+        return None;
+    }
+
+    let max_value = ((1u32 << bpc) - 1) as f32;
+    let sample = |row: &[u8], index: usize| -> u32 {
+        match bpc {
+            8 => row[index] as u32,
+            16 => ((row[index * 2] as u32) << 8) | row[index * 2 + 1] as u32,
+            _ => {
+                let bit = index * bpc as usize;
+                let byte = row[bit / 8] as u32;
+                let shift = 8 - bpc - (bit % 8) as u32;
+                (byte >> shift) & ((1 << bpc) - 1)
+            }
+        }
+    };
+
+    // Map a raw sample to 0.0..=1.0 through the Decode array
+    let default_decode: Vec<f32> = match color_space {
+        ColorSpace::Indexed { .. } => vec![0.0, max_value],
+        _ => (0..components).flat_map(|_| [0.0, 1.0]).collect(),
+    };
+    let decode = match decode {
+        Some(d) if d.len() >= components * 2 => d.to_vec(),
+        _ => default_decode,
+    };
+    let to_unit = |value: u32, component: usize| -> f32 {
+        let (d_min, d_max) = (decode[component * 2], decode[component * 2 + 1]);
+        d_min + value as f32 * (d_max - d_min) / max_value
+    };
+    let to_byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    let rows = || (0..height as usize).map(|y| &data[y * row_bytes..(y + 1) * row_bytes]);
+
+    match color_space {
+        ColorSpace::Gray | ColorSpace::Tint => {
+            let invert = *color_space == ColorSpace::Tint;
+            let mut pixels = Vec::with_capacity(width as usize * height as usize);
+            for row in rows() {
+                for x in 0..width as usize {
+                    let v = to_unit(sample(row, x), 0);
+                    pixels.push(to_byte(if invert { 1.0 - v } else { v }));
+                }
+            }
+            GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+        }
+        ColorSpace::Rgb | ColorSpace::Cmyk => {
+            let mut image = RgbImage::new(width, height);
+            for (y, row) in rows().enumerate() {
+                for x in 0..width as usize {
+                    let c: Vec<f32> = (0..components)
+                        .map(|i| to_unit(sample(row, x * components + i), i))
+                        .collect();
+                    image.put_pixel(x as u32, y as u32, unit_to_rgb(color_space, &c, to_byte));
+                }
+            }
+            Some(DynamicImage::ImageRgb8(image))
+        }
+        ColorSpace::Indexed {
+            base,
+            hival,
+            lookup,
+        } => {
+            let base_components = base.components();
+            let mut image = RgbImage::new(width, height);
+            for (y, row) in rows().enumerate() {
+                for x in 0..width as usize {
+                    let index =
+                        (to_unit(sample(row, x), 0).round().max(0.0) as u32).min(*hival) as usize;
+                    let start = index * base_components;
+                    let entry = lookup.get(start..start + base_components).unwrap_or(&[]);
+                    let c: Vec<f32> = entry.iter().map(|&b| b as f32 / 255.0).collect();
+                    let pixel = if c.len() == base_components {
+                        unit_to_rgb(base, &c, to_byte)
+                    } else {
+                        Rgb([0, 0, 0])
+                    };
+                    image.put_pixel(x as u32, y as u32, pixel);
+                }
+            }
+            Some(DynamicImage::ImageRgb8(image))
+        }
+    }
+}
+
+/// Convert unit-range components in a base colour space to an RGB pixel.
+fn unit_to_rgb(color_space: &ColorSpace, c: &[f32], to_byte: impl Fn(f32) -> u8) -> Rgb<u8> {
+    match color_space {
+        ColorSpace::Gray => Rgb([to_byte(c[0]); 3]),
+        ColorSpace::Tint => Rgb([to_byte(1.0 - c[0]); 3]),
+        ColorSpace::Cmyk => {
+            let k = 1.0 - c[3];
+            Rgb([
+                to_byte((1.0 - c[0]) * k),
+                to_byte((1.0 - c[1]) * k),
+                to_byte((1.0 - c[2]) * k),
+            ])
+        }
+        _ => Rgb([to_byte(c[0]), to_byte(c[1]), to_byte(c[2])]),
+    }
+}
+
+/// Read a strictly positive integer dimension.
+fn positive_integer(object: &Object) -> Option<u32> {
+    match object {
+        Object::Integer(v) if *v > 0 && *v <= u32::MAX as i64 => Some(*v as u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    fn image_stream(dict: Dictionary, content: Vec<u8>) -> Stream {
+        Stream::new(dict, content).with_compression(false)
+    }
+
+    #[test]
+    fn test_one_bit_gray_image() {
+        let doc = Document::with_version("1.5");
+        let stream = image_stream(
+            dictionary! {
+                "Subtype" => "Image",
+                "Width" => 8,
+                "Height" => 1,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 1,
+            },
+            vec![0b1010_0000],
+        );
+        let image = decode_image(&doc, &stream).unwrap().to_luma8();
+        assert_eq!(image.get_pixel(0, 0)[0], 255);
+        assert_eq!(image.get_pixel(1, 0)[0], 0);
+        assert_eq!(image.get_pixel(2, 0)[0], 255);
+        assert_eq!(image.get_pixel(7, 0)[0], 0);
+    }
+
+    #[test]
+    fn test_cmyk_image() {
+        let doc = Document::with_version("1.5");
+        let stream = image_stream(
+            dictionary! {
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 1,
+                "ColorSpace" => "DeviceCMYK",
+                "BitsPerComponent" => 8,
+            },
+            vec![0, 0, 0, 255, 0, 0, 0, 0],
+        );
+        let image = decode_image(&doc, &stream).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_indexed_palette_image() {
+        let doc = Document::with_version("1.5");
+        let stream = image_stream(
+            dictionary! {
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 1,
+                "ColorSpace" => vec![
+                    Object::Name(b"Indexed".to_vec()),
+                    Object::Name(b"DeviceRGB".to_vec()),
+                    1.into(),
+                    Object::string_literal(vec![255, 0, 0, 0, 0, 255]),
+                ],
+                "BitsPerComponent" => 8,
+            },
+            vec![1, 0],
+        );
+        let image = decode_image(&doc, &stream).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 0, 0]);
+    }
+
+    #[test]
+    fn test_flate_with_png_predictor() {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+
+        // Two rows of three gray pixels: "Sub" filter on row 1, "Up" filter on row 2
+        let predicted = vec![1, 10, 5, 5, 2, 1, 1, 1];
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&predicted).unwrap();
+
+        let doc = Document::with_version("1.5");
+        let stream = image_stream(
+            dictionary! {
+                "Subtype" => "Image",
+                "Width" => 3,
+                "Height" => 2,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+                "Filter" => "FlateDecode",
+                "DecodeParms" => dictionary! { "Predictor" => 12, "Columns" => 3 },
+            },
+            encoder.finish().unwrap(),
+        );
+        let image = decode_image(&doc, &stream).unwrap().to_luma8();
+        assert_eq!(image.as_raw(), &vec![10, 15, 20, 11, 16, 21]);
+    }
+
+    #[test]
+    fn test_ccitt_group4_round_trip() {
+        use fax::encoder::Encoder;
+        use fax::{Color, VecWriter};
+
+        // A 16x2 image: left half black, right half white
+        let mut encoder = Encoder::new(VecWriter::new());
+        for _ in 0..2 {
+            let line = (0..16).map(|x| if x < 8 { Color::Black } else { Color::White });
+            encoder.encode_line(line, 16).unwrap();
+        }
+        let data = encoder.finish().unwrap().finish();
+
+        // Too many rows for the decoder is a failure, not a truncated page
+        let params = dictionary! { "K" => -1, "Columns" => 16, "Rows" => 65_538 };
+        assert!(decode_ccitt(&data, 16, 65_538, Some(&params)).is_none());
+
+        let doc = Document::with_version("1.5");
+        let stream = image_stream(
+            dictionary! {
+                "Subtype" => "Image",
+                "Width" => 16,
+                "Height" => 2,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 1,
+                "Filter" => "CCITTFaxDecode",
+                "DecodeParms" => dictionary! { "K" => -1, "Columns" => 16, "Rows" => 2 },
+            },
+            data,
+        );
+        let image = decode_image(&doc, &stream).unwrap().to_luma8();
+        assert_eq!(image.get_pixel(0, 1)[0], 0);
+        assert_eq!(image.get_pixel(15, 1)[0], 255);
+    }
+}
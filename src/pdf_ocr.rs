@@ -0,0 +1,706 @@
+//! OCR for scanned PDF pages.
+//!
+//! Pages are processed one at a time. Each page's content stream is walked in
+//! order to find the image XObjects it paints (including images nested inside
+//! form XObjects) along with the transformation they are painted with. The
+//! images are then composited onto a page-sized canvas and OCR'd together, so
+//! tiled or strip-encoded scans are read as one page and the text stays
+//! attached to the page it came from.
+//!
+//! The OCR cache holds each page's text under the PDF's bytes and the page
+//! number, so a page seen before is found before anything is decoded or
+//! rendered, and a scan whose wanted pages are all cached is not even parsed.
+
+use crate::extractors::{ocr_images, ExtractionOptions, OcrImage, OCR_BUDGET_EXHAUSTED};
+use crate::ocr_cache::OcrCache;
+use crate::pdf_image::{decode_image, get_stream_filters};
+use image::{DynamicImage, GrayImage, ImageFormat, Luma};
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Resolution scanned pages are rendered at before OCR, in pixels per inch.
+const RENDER_DPI: f32 = 300.0;
+
+/// Longest edge of a rendered page, to keep oversized pages from exhausting memory.
+const MAX_CANVAS_EDGE: f32 = 7000.0;
+
+/// Rendered images smaller than this (in canvas pixels) are rules, bullets or specks.
+const MIN_PAINTED_EDGE: f32 = 8.0;
+
+/// Maximum nesting depth for form XObjects.
+const MAX_FORM_DEPTH: usize = 12;
+
+/// Version of the page cache keys. Increment when a change to rendering changes
+/// the text a page would get, so pages cached before the change are OCR'd again.
+const PAGE_KEY_VERSION: u32 = 1;
+
+/// Default page size (US Letter, in points) when a page has no usable MediaBox.
+const DEFAULT_MEDIA_BOX: [f32; 4] = [0.0, 0.0, 612.0, 792.0];
+
+/// A PDF transformation matrix `[a b c d e f]`.
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// An image XObject painted on a page, with the CTM in effect when it was painted.
+struct PlacedImage {
+    id: ObjectId,
+    ctm: Matrix,
+}
+
+/// Text recognised in a PDF's scanned pages.
+pub struct PdfOcrText {
+    /// Recognised text keyed by 1-based page number.
+    pub pages: BTreeMap<u32, String>,
+    /// Whether some wanted pages were left out because the OCR budget ran out.
+    pub budget_exhausted: bool,
+}
+
+/// OCR the pages of a PDF for which `wanted` returns true. `page_count` is the
+/// number of pages, if known without parsing the PDF (0 otherwise).
+///
+/// Pages that paint no images, or whose images could not be decoded, are left
+/// out. An image XObject shared by several pages (a letterhead, a logo) is
+/// only read on the first page that paints it. Every rendered page that is
+/// not in the OCR cache counts against the OCR budget; once it is exhausted
+/// the remaining pages are skipped.
+pub fn ocr_pdf_pages(
+    path: &Path,
+    page_count: u32,
+    wanted: impl Fn(u32) -> bool,
+    options: &ExtractionOptions,
+) -> Result<PdfOcrText, String> {
+    let cache = options
+        .ocr_cache
+        .as_deref()
+        .zip(std::fs::read(path).ok())
+        .map(|(cache, bytes)| PageCache::new(cache, &bytes));
+
+    // When every wanted page is cached, the PDF need not be parsed
+    if let Some(cache) = &cache {
+        let cached: Option<Vec<(u32, String)>> = (1..=page_count)
+            .filter(|&page| wanted(page))
+            .map(|page| Some((page, cache.get(page)?)))
+            .collect();
+        if let Some(cached) = cached.filter(|pages| !pages.is_empty()) {
+            return Ok(PdfOcrText {
+                pages: cached
+                    .into_iter()
+                    .filter(|(_, text)| !text.trim().is_empty())
+                    .collect(),
+                budget_exhausted: false,
+            });
+        }
+    }
+
+    let doc = Document::load(path).map_err(|e| format!("Failed to parse PDF for OCR: {}", e))?;
+
+    let mut page_texts = BTreeMap::new();
+    let mut seen_images: HashSet<ObjectId> = HashSet::new();
+
+    for (page_number, page_id) in doc.get_pages() {
+        if !wanted(page_number) {
+            continue;
+        }
+
+        let mut placed = Vec::new();
+        collect_page_images(&doc, page_id, &mut placed);
+
+        // Leave out images an earlier page already contributed
+        placed.retain(|image| !seen_images.contains(&image.id));
+
+        // A cached page is not rendered; pages without text are cached as empty
+        if let Some(text) = cache.as_ref().and_then(|c| c.get(page_number)) {
+            if !text.is_empty() {
+                seen_images.extend(placed.iter().map(|image| image.id));
+            }
+            if !text.trim().is_empty() {
+                page_texts.insert(page_number, text);
+            }
+            continue;
+        }
+        let remember = |text: &str| {
+            if let Some(cache) = &cache {
+                cache.put(page_number, text);
+            }
+        };
+
+        if placed.is_empty() {
+            remember("");
+            continue;
+        }
+
+        let images = render_page(&doc, page_id, &placed);
+        if images.is_empty() {
+            remember("");
+            continue;
+        }
+
+        let result = ocr_images(&images, options);
+        if result.error.as_deref() == Some(OCR_BUDGET_EXHAUSTED) {
+            return Ok(PdfOcrText {
+                pages: page_texts,
+                budget_exhausted: true,
+            });
+        }
+        seen_images.extend(placed.iter().map(|image| image.id));
+
+        if result.success {
+            remember(&result.text);
+            if !result.text.trim().is_empty() {
+                page_texts.insert(page_number, result.text);
+            }
+        }
+    }
+
+    Ok(PdfOcrText {
+        pages: page_texts,
+        budget_exhausted: false,
+    })
+}
+
+/// The OCR cache, keyed by page number within one PDF.
+struct PageCache<'a> {
+    cache: &'a OcrCache,
+    /// SHA-256 of the PDF's bytes.
+    file_hash: [u8; 32],
+}
+
+impl<'a> PageCache<'a> {
+    fn new(cache: &'a OcrCache, pdf: &[u8]) -> Self {
+        Self {
+            cache,
+            file_hash: Sha256::digest(pdf).into(),
+        }
+    }
+
+    /// The cache key of a page: the PDF, the page number and the render settings.
+    fn key(&self, page: u32) -> String {
+        let mut content = format!(
+            "pdf-page v{} dpi={} max-edge={} min-edge={} page={} ",
+            PAGE_KEY_VERSION, RENDER_DPI, MAX_CANVAS_EDGE, MIN_PAINTED_EDGE, page
+        )
+        .into_bytes();
+        content.extend_from_slice(&self.file_hash);
+        self.cache.key(&content)
+    }
+
+    fn get(&self, page: u32) -> Option<String> {
+        self.cache.get(&self.key(page))
+    }
+
+    fn put(&self, page: u32, text: &str) {
+        // A cache that cannot be written to only costs a repeat OCR later
+        let _ = self.cache.put(&self.key(page), text);
+    }
+}
+
+/// Collect the images painted by a page, in content-stream order.
+fn collect_page_images(doc: &Document, page_id: ObjectId, placed: &mut Vec<PlacedImage>) {
+    let content = match doc.get_page_content(page_id) {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+    let resources = page_resources(doc, page_id);
+    walk_content(doc, &content, &resources, IDENTITY, 0, placed);
+}
+
+/// Gather the resource dictionaries that apply to a page, nearest first.
+fn page_resources(doc: &Document, page_id: ObjectId) -> Vec<&Dictionary> {
+    let mut dicts = Vec::new();
+    if let Ok((direct, ids)) = doc.get_page_resources(page_id) {
+        dicts.extend(direct);
+        dicts.extend(ids.into_iter().filter_map(|id| doc.get_dictionary(id).ok()));
+    }
+    dicts
+}
+
+/// Interpret a content stream far enough to track the CTM and find `Do` operators.
+fn walk_content(
+    doc: &Document,
+    content: &[u8],
+    resources: &[&Dictionary],
+    base_ctm: Matrix,
+    depth: usize,
+    placed: &mut Vec<PlacedImage>,
+) {
+    let content = match Content::decode(content) {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+
+    let mut ctm = base_ctm;
+    let mut saved: Vec<Matrix> = Vec::new();
+
+    for op in &content.operations {
+        match op.operator.as_str() {
+            "q" => saved.push(ctm),
+            "Q" => ctm = saved.pop().unwrap_or(base_ctm),
+            "cm" => {
+                if let Some(m) = operands_to_matrix(&op.operands) {
+                    ctm = multiply(&m, &ctm);
+                }
+            }
+            "Do" => {
+                let name = match op.operands.first().and_then(|o| o.as_name().ok()) {
+                    Some(n) => n,
+                    None => continue,
+                };
+                let (id, stream) = match lookup_xobject(doc, resources, name) {
+                    Some(x) => x,
+                    None => continue,
+                };
+
+                match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                    Ok(b"Image") => placed.push(PlacedImage { id, ctm }),
+                    Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                        let form_ctm = stream
+                            .dict
+                            .get(b"Matrix")
+                            .and_then(Object::as_array)
+                            .ok()
+                            .and_then(|arr| operands_to_matrix(arr))
+                            .map(|m| multiply(&m, &ctm))
+                            .unwrap_or(ctm);
+
+                        // Forms without their own resources inherit the caller's
+                        let mut form_resources = Vec::new();
+                        if let Ok(res) = stream.dict.get(b"Resources") {
+                            if let Ok((_, Object::Dictionary(dict))) = doc.dereference(res) {
+                                form_resources.push(dict);
+                            }
+                        }
+                        form_resources.extend_from_slice(resources);
+
+                        let form_content = stream
+                            .decompressed_content()
+                            .unwrap_or_else(|_| stream.content.clone());
+                        walk_content(
+                            doc,
+                            &form_content,
+                            &form_resources,
+                            form_ctm,
+                            depth + 1,
+                            placed,
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Resolve a named XObject through a chain of resource dictionaries.
+fn lookup_xobject<'a>(
+    doc: &'a Document,
+    resources: &[&'a Dictionary],
+    name: &[u8],
+) -> Option<(ObjectId, &'a Stream)> {
+    for res in resources {
+        let xobjects = match res
+            .get(b"XObject")
+            .ok()
+            .and_then(|o| doc.dereference(o).ok())
+        {
+            Some((_, Object::Dictionary(dict))) => dict,
+            _ => continue,
+        };
+        if let Ok(Object::Reference(id)) = xobjects.get(name) {
+            if let Ok(Object::Stream(stream)) = doc.get_object(*id) {
+                return Some((*id, stream));
+            }
+        }
+    }
+    None
+}
+
+/// Composite a page's images onto a canvas, returning the images to OCR.
+fn render_page(doc: &Document, page_id: ObjectId, placed: &[PlacedImage]) -> Vec<OcrImage> {
+    let media_box = page_media_box(doc, page_id);
+    let page_width = (media_box[2] - media_box[0]).abs().max(1.0);
+    let page_height = (media_box[3] - media_box[1]).abs().max(1.0);
+
+    let scale = (RENDER_DPI / 72.0).min(MAX_CANVAS_EDGE / page_width.max(page_height));
+    let canvas_width = (page_width * scale).ceil() as u32;
+    let canvas_height = (page_height * scale).ceil() as u32;
+
+    let mut canvas = GrayImage::from_pixel(canvas_width, canvas_height, Luma([255]));
+    let mut painted = 0;
+    let mut images: Vec<OcrImage> = Vec::new();
+
+    for image in placed {
+        let stream = match doc.get_object(image.id).and_then(Object::as_stream) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+
+        // Map the image's unit square into canvas pixels (y grows downwards)
+        let to_canvas = multiply(
+            &image.ctm,
+            &[
+                scale,
+                0.0,
+                0.0,
+                -scale,
+                -media_box[0] * scale,
+                media_box[3] * scale,
+            ],
+        );
+        let (width, height) = painted_size(&to_canvas);
+        if width < MIN_PAINTED_EDGE || height < MIN_PAINTED_EDGE {
+            continue;
+        }
+
+        match decode_image(doc, stream) {
+            Some(decoded) => {
+                paint_image(&mut canvas, &decoded.to_luma8(), &to_canvas);
+                painted += 1;
+            }
+            None => {
+                // Formats we cannot decode ourselves are handed to Leptonica as-is
+                if let Some(image) = raw_stream_image(stream) {
+                    images.push(image);
+                }
+            }
+        }
+    }
+
+    if painted > 0 {
+        let canvas = rotate_for_page(
+            DynamicImage::ImageLuma8(canvas),
+            page_rotation(doc, page_id),
+        );
+        if let Some(image) = encode_canvas(&canvas) {
+            images.insert(0, image);
+        }
+    }
+
+    images
+}
+
+/// Paint a grayscale image onto the canvas through the given unit-square transform.
+fn paint_image(canvas: &mut GrayImage, image: &GrayImage, to_canvas: &Matrix) {
+    let inverse = match invert(to_canvas) {
+        Some(m) => m,
+        None => return,
+    };
+    let (img_w, img_h) = image.dimensions();
+    if img_w == 0 || img_h == 0 {
+        return;
+    }
+
+    // Only visit canvas pixels inside the image's bounding box
+    let corners =
+        [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, y)| apply(to_canvas, x, y));
+    let min_x = corners
+        .iter()
+        .map(|c| c.0)
+        .fold(f32::INFINITY, f32::min)
+        .floor()
+        .max(0.0) as u32;
+    let max_x = corners
+        .iter()
+        .map(|c| c.0)
+        .fold(f32::NEG_INFINITY, f32::max)
+        .ceil()
+        .min(canvas.width() as f32) as u32;
+    let min_y = corners
+        .iter()
+        .map(|c| c.1)
+        .fold(f32::INFINITY, f32::min)
+        .floor()
+        .max(0.0) as u32;
+    let max_y = corners
+        .iter()
+        .map(|c| c.1)
+        .fold(f32::NEG_INFINITY, f32::max)
+        .ceil()
+        .min(canvas.height() as f32) as u32;
+
+    for cy in min_y..max_y {
+        for cx in min_x..max_x {
+            let (u, v) = apply(&inverse, cx as f32 + 0.5, cy as f32 + 0.5);
+            if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                continue;
+            }
+            // Image row 0 is painted at the top of the unit square
+            let px = ((u * img_w as f32) as u32).min(img_w - 1);
+            let py = (((1.0 - v) * img_h as f32) as u32).min(img_h - 1);
+            canvas.put_pixel(cx, cy, *image.get_pixel(px, py));
+        }
+    }
+}
+
+/// Encode a rendered page canvas as PNG for OCR.
+fn encode_canvas(canvas: &DynamicImage) -> Option<OcrImage> {
+    let mut data = Vec::new();
+    canvas
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+        .ok()?;
+    Some(OcrImage {
+        data,
+        extension: "png".to_string(),
+    })
+}
+
+/// Pass through an image stream whose encoding Leptonica reads natively (JPEG 2000).
+fn raw_stream_image(stream: &Stream) -> Option<OcrImage> {
+    let filters = get_stream_filters(&stream.dict);
+    if !filters.iter().any(|f| f == b"JPXDecode") {
+        return None;
+    }
+    Some(OcrImage {
+        data: stream.content.clone(),
+        extension: "jp2".to_string(),
+    })
+}
+
+/// Look up a page attribute, following the page tree for inherited values.
+fn inherited_attribute<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..MAX_FORM_DEPTH {
+        if let Ok(value) = node.get(key) {
+            return doc.dereference(value).ok().map(|(_, o)| o);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// Get a page's MediaBox as `[llx lly urx ury]`.
+fn page_media_box(doc: &Document, page_id: ObjectId) -> [f32; 4] {
+    let values: Vec<f32> = match inherited_attribute(doc, page_id, b"MediaBox") {
+        Some(Object::Array(arr)) => arr.iter().filter_map(|o| o.as_float().ok()).collect(),
+        _ => Vec::new(),
+    };
+    match values.as_slice() {
+        [x0, y0, x1, y1] if x1 != x0 && y1 != y0 => {
+            [x0.min(*x1), y0.min(*y1), x0.max(*x1), y0.max(*y1)]
+        }
+        _ => DEFAULT_MEDIA_BOX,
+    }
+}
+
+/// Get a page's display rotation in degrees (a multiple of 90).
+fn page_rotation(doc: &Document, page_id: ObjectId) -> i64 {
+    match inherited_attribute(doc, page_id, b"Rotate") {
+        Some(Object::Integer(r)) => r.rem_euclid(360),
+        _ => 0,
+    }
+}
+
+/// Turn a rendered page upright according to its /Rotate entry.
+fn rotate_for_page(canvas: DynamicImage, rotation: i64) -> DynamicImage {
+    match rotation {
+        90 => canvas.rotate90(),
+        180 => canvas.rotate180(),
+        270 => canvas.rotate270(),
+        _ => canvas,
+    }
+}
+
+/// Build a matrix from six numeric operands.
+fn operands_to_matrix(operands: &[Object]) -> Option<Matrix> {
+    if operands.len() != 6 {
+        return None;
+    }
+    let mut m = [0.0; 6];
+    for (slot, operand) in m.iter_mut().zip(operands) {
+        *slot = operand.as_float().ok()?;
+    }
+    Some(m)
+}
+
+/// Concatenate two matrices: apply `m` first, then `n`.
+fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+/// Invert a matrix, if it is not degenerate.
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let det = m[0] * m[3] - m[1] * m[2];
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let a = m[3] / det;
+    let b = -m[1] / det;
+    let c = -m[2] / det;
+    let d = m[0] / det;
+    Some([a, b, c, d, -(m[4] * a + m[5] * c), -(m[4] * b + m[5] * d)])
+}
+
+/// Transform a point by a matrix.
+fn apply(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (x * m[0] + y * m[2] + m[4], x * m[1] + y * m[3] + m[5])
+}
+
+/// Size of the unit square after transformation, as (width, height) of its edges.
+fn painted_size(m: &Matrix) -> (f32, f32) {
+    (m[0].hypot(m[1]), m[2].hypot(m[3]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::content::Operation;
+    use lopdf::{dictionary, Stream};
+
+    /// Build a PDF where each page paints the given image XObjects.
+    fn build_pdf(pages: &[&[&str]]) -> Document {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+
+        let mut xobjects = Dictionary::new();
+        for name in ["Im1", "Im2", "Im3"] {
+            let image = Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => 2,
+                    "Height" => 2,
+                    "ColorSpace" => "DeviceGray",
+                    "BitsPerComponent" => 8,
+                },
+                vec![0, 255, 255, 0],
+            );
+            xobjects.set(name, doc.add_object(image));
+        }
+        let resources_id = doc.add_object(dictionary! { "XObject" => xobjects });
+
+        let mut kids = Vec::new();
+        for names in pages {
+            let mut operations = Vec::new();
+            for (i, name) in names.iter().enumerate() {
+                operations.push(Operation::new("q", vec![]));
+                operations.push(Operation::new(
+                    "cm",
+                    vec![
+                        200.into(),
+                        0.into(),
+                        0.into(),
+                        100.into(),
+                        0.into(),
+                        (i as i64 * 100).into(),
+                    ],
+                ));
+                operations.push(Operation::new(
+                    "Do",
+                    vec![Object::Name(name.as_bytes().to_vec())],
+                ));
+                operations.push(Operation::new("Q", vec![]));
+            }
+            let content = Content { operations };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            });
+            kids.push(page_id.into());
+        }
+
+        let count = kids.len() as i64;
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc
+    }
+
+    #[test]
+    fn test_images_collected_in_content_order() {
+        let doc = build_pdf(&[&["Im2", "Im1", "Im3"]]);
+        let page_id = doc.get_pages()[&1];
+
+        let mut placed = Vec::new();
+        collect_page_images(&doc, page_id, &mut placed);
+        assert_eq!(placed.len(), 3);
+
+        // Each image keeps the CTM it was painted with
+        assert_eq!(placed[0].ctm, [200.0, 0.0, 0.0, 100.0, 0.0, 0.0]);
+        assert_eq!(placed[2].ctm[5], 200.0);
+    }
+
+    #[test]
+    fn test_media_box_inherited_from_page_tree() {
+        let doc = build_pdf(&[&["Im1"]]);
+        let page_id = doc.get_pages()[&1];
+        assert_eq!(page_media_box(&doc, page_id), [0.0, 0.0, 612.0, 792.0]);
+        assert_eq!(page_rotation(&doc, page_id), 0);
+    }
+
+    #[test]
+    fn test_cached_pages_skip_parsing() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = OcrCache::open(dir.path().join("cache"), 1024 * 1024, "eng").unwrap();
+        // Not a PDF: a cache miss would fail to parse
+        let path = dir.path().join("scan.pdf");
+        std::fs::write(&path, b"not a pdf").unwrap();
+
+        let pages = PageCache::new(&cache, b"not a pdf");
+        pages.put(1, "first page");
+        pages.put(2, "");
+        pages.put(3, "third page");
+
+        let options = ExtractionOptions {
+            ocr_enabled: true,
+            ocr_cache: Some(std::sync::Arc::new(cache)),
+            ..Default::default()
+        };
+        let text = ocr_pdf_pages(&path, 3, |page| page != 3, &options).unwrap();
+        assert_eq!(
+            text.pages.into_iter().collect::<Vec<_>>(),
+            vec![(1, "first page".to_string())]
+        );
+        assert!(!text.budget_exhausted);
+
+        // Page 4 is not cached, so the file has to be parsed
+        assert!(ocr_pdf_pages(&path, 4, |_| true, &options).is_err());
+    }
+
+    #[test]
+    fn test_matrix_inverse_round_trip() {
+        let m = multiply(
+            &[2.0, 0.0, 0.0, 3.0, 10.0, 20.0],
+            &[0.0, 1.0, -1.0, 0.0, 5.0, 5.0],
+        );
+        let inv = invert(&m).unwrap();
+        let (x, y) = apply(&m, 0.25, 0.75);
+        let (u, v) = apply(&inv, x, y);
+        assert!((u - 0.25).abs() < 1e-4);
+        assert!((v - 0.75).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_paint_image_places_pixels() {
+        let mut canvas = GrayImage::from_pixel(4, 4, Luma([255]));
+        let image = GrayImage::from_raw(2, 2, vec![0, 255, 255, 0]).unwrap();
+        // Unit square scaled to 4x4 canvas pixels, flipped so row 0 is at the top
+        paint_image(&mut canvas, &image, &[4.0, 0.0, 0.0, -4.0, 0.0, 4.0]);
+        assert_eq!(canvas.get_pixel(0, 0)[0], 0);
+        assert_eq!(canvas.get_pixel(3, 0)[0], 255);
+        assert_eq!(canvas.get_pixel(3, 3)[0], 0);
+    }
+}
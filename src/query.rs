@@ -0,0 +1,563 @@
+//! Boolean query language.
+//!
+//! Queries combine search terms with `AND`, `OR` and `NOT` (upper case),
+//! parentheses and double-quoted phrases. Terms next to each other without an
+//! operator are ANDed, and `NOT` binds tightest, then `AND`, then `OR`:
+//!
+//! ```text
+//! invoice 2023 NOT draft
+//! (invoice OR receipt) AND "paid in full"
+//! ```
+//!
+//! `a NEAR/N b` holds where `a` and `b` occur within N words of each other, in
+//! either order and across line breaks (`NEAR` alone means `NEAR/10`). Its
+//! operands must be terms; `a NEAR/5 b NEAR/5 c` requires both pairs.
+//!
+//! A query is evaluated once per file: each term either occurs somewhere in the
+//! file or it does not.
+//!
+//! Qualifiers such as `type:pdf` (see [`crate::filters`]) restrict the files the
+//! whole query is evaluated on, so they may only be ANDed with the rest of the
+//! query: `invoice AND type:pdf` is fine, `invoice OR type:pdf` is an error.
+
+use crate::filters::{is_qualifier, FileFilters};
+use std::fmt;
+
+/// Word distance used by `NEAR` without an explicit `/N`.
+pub const DEFAULT_NEAR_DISTANCE: usize = 10;
+
+/// A parsed boolean expression over search terms.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// A search term, by index into [`ParsedQuery::terms`].
+    Term(usize),
+    /// All sub-queries must hold.
+    And(Vec<Query>),
+    /// At least one sub-query must hold.
+    Or(Vec<Query>),
+    /// The sub-query must not hold.
+    Not(Box<Query>),
+    /// Two terms occurring within `distance` words of each other.
+    Near {
+        left: usize,
+        right: usize,
+        distance: usize,
+    },
+}
+
+impl Query {
+    /// Evaluate the query given which leaves (terms and `NEAR` pairs) hold in a file.
+    pub fn eval(&self, matched: &impl Fn(&Query) -> bool) -> bool {
+        match self {
+            Query::Term(_) | Query::Near { .. } => matched(self),
+            Query::And(parts) => parts.iter().all(|q| q.eval(matched)),
+            Query::Or(parts) => parts.iter().any(|q| q.eval(matched)),
+            Query::Not(inner) => !inner.eval(matched),
+        }
+    }
+
+    /// Collect the distinct leaves (terms and `NEAR` pairs) of the query.
+    pub fn leaves(&self) -> Vec<&Query> {
+        let mut leaves = Vec::new();
+        self.collect_leaves(false, false, &mut leaves);
+        leaves
+    }
+
+    /// Collect the leaves that count towards a match (those not negated).
+    pub fn positive_leaves(&self) -> Vec<&Query> {
+        let mut leaves = Vec::new();
+        self.collect_leaves(false, true, &mut leaves);
+        leaves
+    }
+
+    fn collect_leaves<'a>(
+        &'a self,
+        negated: bool,
+        positive_only: bool,
+        leaves: &mut Vec<&'a Query>,
+    ) {
+        match self {
+            Query::Term(_) | Query::Near { .. } => {
+                let skipped = negated && positive_only;
+                if !skipped && !leaves.contains(&self) {
+                    leaves.push(self);
+                }
+            }
+            Query::And(parts) | Query::Or(parts) => {
+                for part in parts {
+                    part.collect_leaves(negated, positive_only, leaves);
+                }
+            }
+            Query::Not(inner) => inner.collect_leaves(!negated, positive_only, leaves),
+        }
+    }
+
+    /// Describe a leaf using the query's term texts.
+    pub fn describe(&self, terms: &[String]) -> String {
+        match self {
+            Query::Term(term) => terms[*term].clone(),
+            Query::Near {
+                left,
+                right,
+                distance,
+            } => format!("{} NEAR/{} {}", terms[*left], distance, terms[*right]),
+            Query::And(_) | Query::Or(_) | Query::Not(_) => String::new(),
+        }
+    }
+}
+
+/// A query together with the distinct terms it refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedQuery {
+    /// The boolean expression (`And([])`, which always holds, if there are only qualifiers).
+    pub expr: Query,
+    /// Search terms, in order of first appearance.
+    pub terms: Vec<String>,
+    /// Filters from the query's qualifiers.
+    pub filters: FileFilters,
+}
+
+/// Errors that can occur while parsing a query.
+#[derive(Debug, PartialEq)]
+pub enum QueryError {
+    /// The query contains no terms.
+    Empty,
+    /// A quoted phrase is missing its closing quote.
+    UnclosedQuote,
+    /// A `(` is missing its `)`, or a `)` has no `(`.
+    UnbalancedParens,
+    /// An operator is missing an operand.
+    MissingOperand(String),
+    /// Every term is negated, so no file could be reported.
+    OnlyNegated,
+    /// A `NEAR/N` operator with an invalid distance.
+    InvalidDistance(String),
+    /// A `NEAR` operand that is not a term.
+    NearNeedsTerms,
+    /// A qualifier under `OR` or `NOT`.
+    MisplacedQualifier(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Empty => write!(f, "Query is empty"),
+            QueryError::UnclosedQuote => write!(f, "Unclosed quote in query"),
+            QueryError::UnbalancedParens => write!(f, "Unbalanced parentheses in query"),
+            QueryError::MissingOperand(op) => write!(f, "Missing search term after {}", op),
+            QueryError::OnlyNegated => {
+                write!(f, "Query needs at least one term that is not negated with NOT")
+            }
+            QueryError::InvalidDistance(op) => write!(f, "Invalid distance in {}", op),
+            QueryError::NearNeedsTerms => write!(f, "NEAR can only combine terms or phrases"),
+            QueryError::MisplacedQualifier(qualifier) => write!(
+                f,
+                "Qualifier {} can only be ANDed with the query, not used with OR or NOT \
+                 (write \\{} to search for it)",
+                qualifier, qualifier
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// A lexical token of the query language.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    /// A file qualifier such as `type:pdf`.
+    Qualifier(String),
+    And,
+    Or,
+    Not,
+    Near(usize),
+    Open,
+    Close,
+}
+
+/// Parse a query string, taking words such as `type:pdf` as qualifiers if
+/// `qualifiers` is set (a backslash before one makes it a term again).
+pub fn parse(input: &str, qualifiers: bool) -> Result<ParsedQuery, QueryError> {
+    let tokens = tokenize(input, qualifiers)?;
+    if tokens.is_empty() {
+        return Err(QueryError::Empty);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        terms: Vec::new(),
+        qualifiers: Vec::new(),
+        negated: false,
+    };
+    let expr = parser.parse_or()?;
+    // Everything but a stray `)` or a misplaced NEAR is consumed by the grammar
+    match parser.peek() {
+        Some(Token::Near(_)) => return Err(QueryError::NearNeedsTerms),
+        Some(_) => return Err(QueryError::UnbalancedParens),
+        None => {}
+    }
+
+    // A query of only qualifiers is left for the caller to reject
+    if expr.positive_leaves().is_empty() && !parser.terms.is_empty() {
+        return Err(QueryError::OnlyNegated);
+    }
+
+    let mut filters = FileFilters::default();
+    for qualifier in &parser.qualifiers {
+        filters.add_qualifier(qualifier);
+    }
+    Ok(ParsedQuery {
+        expr,
+        terms: parser.terms,
+        filters,
+    })
+}
+
+/// Split a query into tokens, recognising qualifiers if `qualifiers` is set.
+fn tokenize(input: &str, qualifiers: bool) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut phrase = String::new();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        // Backslash escapes a quote (or another backslash) inside a phrase
+                        '\\' if matches!(chars.peek(), Some('"') | Some('\\')) => {
+                            phrase.push(chars.next().unwrap());
+                        }
+                        c => phrase.push(c),
+                    }
+                }
+                if !closed {
+                    return Err(QueryError::UnclosedQuote);
+                }
+                tokens.push(Token::Phrase(phrase));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    "NEAR" => Token::Near(DEFAULT_NEAR_DISTANCE),
+                    _ => match word.strip_prefix("NEAR/") {
+                        Some(n) => Token::Near(
+                            n.parse()
+                                .map_err(|_| QueryError::InvalidDistance(word.clone()))?,
+                        ),
+                        None if qualifiers && is_qualifier(&word) => Token::Qualifier(word),
+                        None => match word.strip_prefix('\\') {
+                            Some(escaped) if qualifiers && is_qualifier(escaped) => {
+                                Token::Word(escaped.to_string())
+                            }
+                            _ => Token::Word(word),
+                        },
+                    },
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over tokens.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    terms: Vec<String>,
+    /// Qualifiers found so far.
+    qualifiers: Vec<String>,
+    /// Whether the parser is inside the operand of a `NOT`.
+    negated: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// or := and (OR and)*
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let seen = self.qualifiers.len();
+        let mut parts = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            parts.push(self.parse_operand("OR", Self::parse_and)?);
+        }
+        // Qualifiers in any alternative would apply to all of them
+        if parts.len() > 1 {
+            if let Some(qualifier) = self.qualifiers.get(seen) {
+                return Err(QueryError::MisplacedQualifier(qualifier.clone()));
+            }
+        }
+        Ok(if parts.len() == 1 {
+            parts.pop().unwrap()
+        } else {
+            Query::Or(parts)
+        })
+    }
+
+    /// and := not ([AND] not)*
+    fn parse_and(&mut self) -> Result<Query, QueryError> {
+        let mut parts = vec![self.parse_not()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.pos += 1;
+                    parts.push(self.parse_operand("AND", Self::parse_not)?);
+                }
+                // Adjacent terms are implicitly ANDed
+                Some(
+                    Token::Word(_) | Token::Phrase(_) | Token::Qualifier(_) | Token::Not | Token::Open,
+                ) => {
+                    parts.push(self.parse_not()?);
+                }
+                _ => break,
+            }
+        }
+        // Qualifiers leave an empty `And`, which always holds
+        parts.retain(|part| !matches!(part, Query::And(inner) if inner.is_empty()));
+        Ok(if parts.len() == 1 {
+            parts.pop().unwrap()
+        } else {
+            Query::And(parts)
+        })
+    }
+
+    /// not := NOT not | primary
+    fn parse_not(&mut self) -> Result<Query, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            let negated = std::mem::replace(&mut self.negated, true);
+            let inner = self.parse_operand("NOT", Self::parse_not);
+            self.negated = negated;
+            return Ok(Query::Not(Box::new(inner?)));
+        }
+        self.parse_primary()
+    }
+
+    /// primary := term (NEAR term)* | "(" or ")"
+    fn parse_primary(&mut self) -> Result<Query, QueryError> {
+        match self.peek().cloned() {
+            Some(Token::Qualifier(text)) => {
+                self.pos += 1;
+                if self.negated {
+                    return Err(QueryError::MisplacedQualifier(text));
+                }
+                if let Some(Token::Near(_)) = self.peek() {
+                    return Err(QueryError::NearNeedsTerms);
+                }
+                self.qualifiers.push(text);
+                Ok(Query::And(Vec::new()))
+            }
+            Some(Token::Word(text) | Token::Phrase(text)) => {
+                self.pos += 1;
+                let term = self.term_index(text);
+                self.parse_near(term)
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let inner = self.parse_or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(QueryError::UnbalancedParens);
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(Token::Close) => Err(QueryError::UnbalancedParens),
+            Some(Token::And) => Err(QueryError::MissingOperand("AND".to_string())),
+            Some(Token::Or) => Err(QueryError::MissingOperand("OR".to_string())),
+            Some(Token::Near(_)) => Err(QueryError::NearNeedsTerms),
+            Some(Token::Not) | None => Err(QueryError::Empty),
+        }
+    }
+
+    /// Parse any `NEAR/N term` operators following a term; a chain requires every pair.
+    fn parse_near(&mut self, first: usize) -> Result<Query, QueryError> {
+        let mut left = first;
+        let mut pairs = Vec::new();
+        while let Some(&Token::Near(distance)) = self.peek() {
+            self.pos += 1;
+            let right = match self.peek().cloned() {
+                Some(Token::Word(text) | Token::Phrase(text)) => {
+                    self.pos += 1;
+                    self.term_index(text)
+                }
+                None | Some(Token::Close) => {
+                    return Err(QueryError::MissingOperand(format!("NEAR/{}", distance)))
+                }
+                Some(_) => return Err(QueryError::NearNeedsTerms),
+            };
+            pairs.push(Query::Near {
+                left,
+                right,
+                distance,
+            });
+            left = right;
+        }
+
+        Ok(match pairs.len() {
+            0 => Query::Term(first),
+            1 => pairs.pop().unwrap(),
+            _ => Query::And(pairs),
+        })
+    }
+
+    /// Parse the operand of an operator, reporting a missing operand by name.
+    fn parse_operand(
+        &mut self,
+        op: &str,
+        parse: fn(&mut Self) -> Result<Query, QueryError>,
+    ) -> Result<Query, QueryError> {
+        if self.peek().is_none() || self.peek() == Some(&Token::Close) {
+            return Err(QueryError::MissingOperand(op.to_string()));
+        }
+        parse(self)
+    }
+
+    /// Index of a term, adding it if it has not been seen yet.
+    fn term_index(&mut self, text: String) -> usize {
+        match self.terms.iter().position(|t| *t == text) {
+            Some(idx) => idx,
+            None => {
+                self.terms.push(text);
+                self.terms.len() - 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedence_and_implicit_and() {
+        let query = parse(r#"invoice 2023 OR "paid in full" NOT draft"#, false).unwrap();
+        assert_eq!(query.terms, vec!["invoice", "2023", "paid in full", "draft"]);
+        assert_eq!(
+            query.expr,
+            Query::Or(vec![
+                Query::And(vec![Query::Term(0), Query::Term(1)]),
+                Query::And(vec![Query::Term(2), Query::Not(Box::new(Query::Term(3)))]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_eval_with_grouping() {
+        let query = parse("(invoice OR receipt) AND NOT draft", false).unwrap();
+        let file = |present: &[usize]| {
+            let present = present.to_vec();
+            query
+                .expr
+                .eval(&move |leaf| matches!(leaf, Query::Term(t) if present.contains(t)))
+        };
+        assert!(file(&[0]));
+        assert!(file(&[1]));
+        assert!(!file(&[0, 2]));
+        assert!(!file(&[2]));
+
+        assert_eq!(
+            query.expr.positive_leaves(),
+            vec![&Query::Term(0), &Query::Term(1)]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("", false), Err(QueryError::Empty));
+        assert_eq!(parse("\"open", false), Err(QueryError::UnclosedQuote));
+        assert_eq!(parse("(a OR b", false), Err(QueryError::UnbalancedParens));
+        assert_eq!(parse("a)", false), Err(QueryError::UnbalancedParens));
+        assert_eq!(parse("a AND", false), Err(QueryError::MissingOperand("AND".to_string())));
+        assert_eq!(parse("NOT a", false), Err(QueryError::OnlyNegated));
+        assert_eq!(
+            parse("a NEAR/x b", false),
+            Err(QueryError::InvalidDistance("NEAR/x".to_string()))
+        );
+        assert_eq!(parse("(a) NEAR/3 b", false), Err(QueryError::NearNeedsTerms));
+    }
+
+    #[test]
+    fn test_qualifiers() {
+        let query = parse(r#"(invoice OR receipt) AND type:pdf NOT draft ext:md \path:x"#, true).unwrap();
+        assert_eq!(query.terms, vec!["invoice", "receipt", "draft", "path:x"]);
+        assert_eq!(
+            query.expr,
+            Query::And(vec![
+                Query::Or(vec![Query::Term(0), Query::Term(1)]),
+                Query::Not(Box::new(Query::Term(2))),
+                Query::Term(3),
+            ])
+        );
+        assert!(!query.filters.is_empty());
+        assert_eq!(parse("ext:md (type:pdf)", true).unwrap().terms, Vec::<String>::new());
+
+        // Qualifiers restrict the files for the whole query, so they can't be alternatives
+        let misplaced = |q: &str| Err(QueryError::MisplacedQualifier(q.to_string()));
+        assert_eq!(parse("invoice OR type:pdf", true), misplaced("type:pdf"));
+        assert_eq!(parse("(a ext:md) OR b", true), misplaced("ext:md"));
+        assert_eq!(parse("invoice NOT type:pdf", true), misplaced("type:pdf"));
+        assert_eq!(parse("invoice NOT (a type:pdf)", true), misplaced("type:pdf"));
+        assert_eq!(parse("a NEAR type:pdf", true), Err(QueryError::NearNeedsTerms));
+
+        // Without qualifier parsing they are terms
+        assert_eq!(parse("invoice OR type:pdf", false).unwrap().terms, vec!["invoice", "type:pdf"]);
+    }
+
+    #[test]
+    fn test_near_chain() {
+        let query = parse(r#"termination NEAR/10 notice NEAR "thirty days""#, false).unwrap();
+        assert_eq!(
+            query.expr,
+            Query::And(vec![
+                Query::Near {
+                    left: 0,
+                    right: 1,
+                    distance: 10
+                },
+                Query::Near {
+                    left: 1,
+                    right: 2,
+                    distance: DEFAULT_NEAR_DISTANCE
+                },
+            ])
+        );
+        assert_eq!(
+            query.expr.positive_leaves()[0].describe(&query.terms),
+            "termination NEAR/10 notice"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Ordering of search results.
+//!
+//! BM25 scores each file by how often it matches each term, saturating with
+//! repetition and normalized by the file's length, and weighs each term by how rare
+//! it is across the searched files. A long log with hundreds of incidental hits then
+//! no longer outranks a short document that is about the topic.
+//!
+//! Every other order compares two results on their own, so the [`Ranker`] keeps
+//! only the best `limit` results in a heap while the search runs instead of
+//! collecting and sorting all of them.
+
+use crate::types::{Ranking, SearchConfig, SearchResult, Sort, SortKey};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// BM25 term frequency saturation.
+const K1: f64 = 1.2;
+/// BM25 document length normalization (0 = none, 1 = full).
+const B: f64 = 0.75;
+
+/// Word counts of the texts searched, which BM25 compares file lengths against.
+#[derive(Debug, Default)]
+pub struct Corpus {
+    documents: AtomicUsize,
+    words: AtomicUsize,
+}
+
+impl Corpus {
+    /// Record a searched text with this many words.
+    pub fn add(&self, words: usize) {
+        self.documents.fetch_add(1, AtomicOrdering::Relaxed);
+        self.words.fetch_add(words, AtomicOrdering::Relaxed);
+    }
+
+    fn documents(&self) -> usize {
+        self.documents.load(AtomicOrdering::Relaxed)
+    }
+
+    fn average_length(&self) -> f64 {
+        let documents = self.documents().max(1);
+        (self.words.load(AtomicOrdering::Relaxed) as f64 / documents as f64).max(1.0)
+    }
+}
+
+/// The order of results that can be decided one pair at a time, so the best ones
+/// can be kept as they arrive. BM25 is not one: a term's weight depends on how many
+/// of all the matching files contain it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Most matches first, then highest confidence.
+    Count,
+    Sort(Sort),
+}
+
+impl Order {
+    /// The order the configuration asks for, or `None` for BM25. Files without
+    /// matches are listed by path unless a sort is given.
+    pub fn of(config: &SearchConfig) -> Option<Self> {
+        let by = |key, descending| Some(Order::Sort(Sort { key, descending }));
+        if let Some(sort) = config.sort {
+            return Some(Order::Sort(sort));
+        }
+        if config.files_without_match {
+            return by(SortKey::Path, false);
+        }
+        match config.ranking {
+            Ranking::Count => Some(Order::Count),
+            Ranking::Bm25 => None,
+            // Files without a known modification time go last
+            Ranking::Recent => by(SortKey::Modified, true),
+            Ranking::Path => by(SortKey::Path, false),
+        }
+    }
+
+    /// Compare two results; `Less` means `a` is listed first. Ties are broken by
+    /// path so the order does not depend on which thread finished first.
+    pub fn compare(&self, a: &SearchResult, b: &SearchResult) -> Ordering {
+        let ordering = match self {
+            Order::Count => a.cmp(b),
+            Order::Sort(sort) => {
+                let ascending = match sort.key {
+                    SortKey::Path => a.path.cmp(&b.path),
+                    SortKey::Modified => a.modified.cmp(&b.modified),
+                    SortKey::Size => a.file_size.cmp(&b.file_size),
+                    SortKey::Type => a.file_type.to_string().cmp(&b.file_type.to_string()),
+                    SortKey::Count => a.matches.len().cmp(&b.matches.len()),
+                };
+                if sort.descending {
+                    ascending.reverse()
+                } else {
+                    ascending
+                }
+            }
+        };
+        ordering.then_with(|| a.path.cmp(&b.path))
+    }
+}
+
+/// A result in the [`Ranker`]'s heap, ordered so the worst kept result is on top.
+struct Ranked {
+    order: Order,
+    result: SearchResult,
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order.compare(&self.result, &other.result)
+    }
+}
+
+/// Collects results as they are found and returns the best `limit` of them in
+/// order. Unless ranking by BM25, only `limit` results are held at any time.
+pub struct Ranker {
+    order: Option<Order>,
+    limit: usize,
+    kept: BinaryHeap<Ranked>,
+    /// Every result, for BM25.
+    all: Vec<SearchResult>,
+}
+
+impl Ranker {
+    pub fn new(config: &SearchConfig) -> Self {
+        Self {
+            order: Order::of(config),
+            limit: config.limit,
+            kept: BinaryHeap::new(),
+            all: Vec::new(),
+        }
+    }
+
+    /// Add a result, dropping it or the worst kept one if over the limit.
+    pub fn push(&mut self, result: SearchResult) {
+        let Some(order) = self.order else {
+            self.all.push(result);
+            return;
+        };
+        let ranked = Ranked { order, result };
+        if self.kept.len() < self.limit {
+            self.kept.push(ranked);
+        } else if let Some(mut worst) = self.kept.peek_mut() {
+            if ranked < *worst {
+                *worst = ranked;
+            }
+        }
+    }
+
+    /// The best results, in order. BM25 also replaces each result's confidence
+    /// with its score relative to the best one.
+    pub fn finish(self, corpus: &Corpus) -> Vec<SearchResult> {
+        if self.order.is_some() {
+            return self
+                .kept
+                .into_sorted_vec()
+                .into_iter()
+                .map(|ranked| ranked.result)
+                .collect();
+        }
+
+        let mut results = self.all;
+        let scores = bm25_scores(&results, corpus);
+        let best = scores.iter().cloned().fold(0.0, f64::max);
+        for (result, score) in results.iter_mut().zip(scores) {
+            if best > 0.0 {
+                result.confidence = score / best;
+            }
+        }
+        results.sort_by(|a, b| {
+            b.confidence
+                .partial_cmp(&a.confidence)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| Order::Count.compare(a, b))
+        });
+        results.truncate(self.limit);
+        results
+    }
+}
+
+/// BM25 score of each result. The terms are the patterns matches report (query
+/// terms, or each of several patterns); a single pattern is one term. Fuzzy
+/// matches count for less, as in the confidence score.
+fn bm25_scores(results: &[SearchResult], corpus: &Corpus) -> Vec<f64> {
+    let term_frequencies: Vec<HashMap<&str, f64>> = results
+        .iter()
+        .map(|result| {
+            let mut frequencies = HashMap::new();
+            for m in &result.matches {
+                let term = m.pattern.as_deref().unwrap_or("");
+                *frequencies.entry(term).or_insert(0.0) += 1.0 / (1.0 + m.distance as f64);
+            }
+            frequencies
+        })
+        .collect();
+
+    let mut document_frequencies: HashMap<&str, usize> = HashMap::new();
+    for frequencies in &term_frequencies {
+        for &term in frequencies.keys() {
+            *document_frequencies.entry(term).or_insert(0) += 1;
+        }
+    }
+
+    // Every matching file was searched, even if the corpus was not counted
+    let documents = corpus.documents().max(results.len()) as f64;
+    let average_length = corpus.average_length();
+    results
+        .iter()
+        .zip(&term_frequencies)
+        .map(|(result, frequencies)| {
+            let length = result.word_count.max(1) as f64;
+            let norm = K1 * (1.0 - B + B * length / average_length);
+            frequencies
+                .iter()
+                .map(|(term, &tf)| {
+                    let df = document_frequencies[term] as f64;
+                    let idf = (1.0 + (documents - df + 0.5) / (df + 0.5)).ln();
+                    idf * tf * (K1 + 1.0) / (tf + norm)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FileType, Match};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn result(name: &str, hits: &[(&str, usize)], word_count: usize) -> SearchResult {
+        let matches = hits
+            .iter()
+            .flat_map(|&(term, count)| {
+                std::iter::repeat_n(term, count).map(|term| Match {
+                    pattern: Some(Arc::from(term)),
+                    ..Match::new(term.to_string(), String::new(), 1, 1, 0)
+                })
+            })
+            .collect();
+        let mut result = SearchResult::new(PathBuf::from(name), FileType::Text, matches, 0);
+        result.word_count = word_count;
+        result
+    }
+
+    fn ranked(
+        results: Vec<SearchResult>,
+        ranking: Ranking,
+        sort: Option<Sort>,
+        corpus: &Corpus,
+    ) -> Vec<SearchResult> {
+        let config = SearchConfig {
+            ranking,
+            sort,
+            ..SearchConfig::default()
+        };
+        let mut ranker = Ranker::new(&config);
+        for result in results {
+            ranker.push(result);
+        }
+        ranker.finish(corpus)
+    }
+
+    fn names(results: &[SearchResult]) -> Vec<String> {
+        results.iter().map(|r| r.filename()).collect()
+    }
+
+    #[test]
+    fn test_bm25_prefers_focused_documents() {
+        let corpus = Corpus::default();
+        for words in [100_000, 400, 5_000, 5_000, 5_000] {
+            corpus.add(words);
+        }
+        let results = vec![
+            result("huge.log", &[("cache", 500)], 100_000),
+            result("design.md", &[("cache", 12), ("eviction", 6)], 400),
+        ];
+
+        let results = ranked(results, Ranking::Count, None, &corpus);
+        assert_eq!(results[0].filename(), "huge.log");
+
+        let results = ranked(results, Ranking::Bm25, None, &corpus);
+        assert_eq!(results[0].filename(), "design.md");
+        assert_eq!(results[0].confidence, 1.0);
+        assert!(results[1].confidence < 1.0);
+    }
+
+    #[test]
+    fn test_rare_terms_weigh_more() {
+        let corpus = Corpus::default();
+        for _ in 0..10 {
+            corpus.add(1_000);
+        }
+        // "the" is in every result, "raft" in one
+        let results = vec![
+            result("a.txt", &[("the", 10)], 1_000),
+            result("b.txt", &[("the", 3), ("raft", 3)], 1_000),
+            result("c.txt", &[("the", 9)], 1_000),
+        ];
+        let results = ranked(results, Ranking::Bm25, None, &corpus);
+        assert_eq!(results[0].filename(), "b.txt");
+
+        let results = ranked(results, Ranking::Path, None, &corpus);
+        assert_eq!(names(&results), vec!["a.txt", "b.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn test_sort_keeps_top_k() {
+        let corpus = Corpus::default();
+        let results: Vec<SearchResult> = (0..50)
+            .map(|i| {
+                let mut r = result(&format!("f{:02}.txt", i), &[("x", 1 + i % 7)], 0);
+                r.file_size = (i * 37 % 50) as u64;
+                r
+            })
+            .collect();
+        let by = |key, descending| Some(Sort { key, descending });
+
+        let config = SearchConfig {
+            limit: 3,
+            sort: by(SortKey::Size, true),
+            ..SearchConfig::default()
+        };
+        let mut ranker = Ranker::new(&config);
+        for r in results.clone() {
+            ranker.push(r);
+            assert!(ranker.kept.len() <= 3);
+        }
+        let sizes: Vec<u64> = ranker.finish(&corpus).iter().map(|r| r.file_size).collect();
+        assert_eq!(sizes, vec![49, 48, 47]);
+
+        // Equal counts are broken by path
+        let top = ranked(results.clone(), Ranking::Count, by(SortKey::Count, false), &corpus);
+        assert_eq!(names(&top[..3]), vec!["f00.txt", "f07.txt", "f14.txt"]);
+        let top = ranked(results, Ranking::Count, by(SortKey::Path, true), &corpus);
+        assert_eq!(top.len(), 20);
+        assert_eq!(top[0].filename(), "f49.txt");
+    }
+}
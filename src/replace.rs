@@ -0,0 +1,375 @@
+//! Search-and-replace in plain text and code files.
+//!
+//! Edits are planned in memory first, so they can be shown as a diff, and only
+//! written on request. Files are read as they are on disk rather than through the
+//! extractors, since extraction normalizes line endings and truncates long files.
+
+use crate::search::EngineRegex;
+use crate::types::FileType;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Suffix of the backup kept for each edited file.
+const BACKUP_SUFFIX: &str = ".bak";
+
+/// Replaces the matches of a compiled search pattern.
+pub struct Replacer {
+    regex: EngineRegex,
+    replacement: String,
+    /// Whether `$1` and `${name}` in the replacement refer to capture groups.
+    expand: bool,
+    /// Whether matches may span lines (otherwise each line is replaced on its own).
+    multiline: bool,
+}
+
+/// Errors that can occur while replacing in a file.
+#[derive(Debug)]
+pub enum ReplaceError {
+    /// Only plain text and code files can be edited.
+    Unsupported(FileType),
+    /// The file is not valid UTF-8.
+    NotUtf8,
+    /// The regex failed while matching (e.g. it hit the backtracking limit).
+    Regex(String),
+    /// The backup of an earlier edit is still there.
+    BackupExists(PathBuf),
+    /// Reading or writing the file failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for ReplaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplaceError::Unsupported(file_type) => {
+                write!(
+                    f,
+                    "{} files can't be edited; only text and code files can",
+                    file_type
+                )
+            }
+            ReplaceError::NotUtf8 => write!(f, "not valid UTF-8"),
+            ReplaceError::Regex(e) => write!(f, "regex failed: {}", e),
+            ReplaceError::BackupExists(backup) => write!(
+                f,
+                "backup {} already exists; move it away to edit the file again",
+                backup.display()
+            ),
+            ReplaceError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplaceError {}
+
+impl From<io::Error> for ReplaceError {
+    fn from(e: io::Error) -> Self {
+        ReplaceError::Io(e)
+    }
+}
+
+/// Whether a file is a backup kept by [`FileEdit::write`].
+pub fn is_backup(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(BACKUP_SUFFIX)
+}
+
+impl Replacer {
+    /// Create a replacer for the matches of `regex`.
+    pub(crate) fn new(
+        regex: EngineRegex,
+        replacement: &str,
+        expand: bool,
+        multiline: bool,
+    ) -> Self {
+        Self {
+            regex,
+            replacement: replacement.to_string(),
+            expand,
+            multiline,
+        }
+    }
+
+    /// Replace every match in `text`, returning the new text and the number of replacements.
+    pub fn apply(&self, text: &str) -> Result<(String, usize), ReplaceError> {
+        if self.multiline {
+            return self.replace_all(text);
+        }
+
+        let mut updated = String::with_capacity(text.len());
+        let mut count = 0;
+        for raw in text.split_inclusive('\n') {
+            let line = raw.strip_suffix('\n').unwrap_or(raw);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let (replaced, n) = self.replace_all(line)?;
+            updated.push_str(&replaced);
+            updated.push_str(&raw[line.len()..]);
+            count += n;
+        }
+        Ok((updated, count))
+    }
+
+    fn replace_all(&self, text: &str) -> Result<(String, usize), ReplaceError> {
+        self.regex
+            .replace_all(text, &self.replacement, self.expand)
+            .map_err(ReplaceError::Regex)
+    }
+}
+
+/// The planned replacements in one file.
+#[derive(Debug)]
+pub struct FileEdit {
+    pub path: PathBuf,
+    pub original: String,
+    pub updated: String,
+    /// Number of matches replaced.
+    pub replacements: usize,
+}
+
+/// A run of changed lines.
+#[derive(Debug, PartialEq)]
+pub struct Hunk<'a> {
+    /// Line number of the first changed line in the original file (1-based).
+    pub line: usize,
+    pub removed: Vec<&'a str>,
+    pub added: Vec<&'a str>,
+}
+
+impl FileEdit {
+    /// Plan the replacements in a file; `None` if nothing would change.
+    pub fn plan(
+        path: &Path,
+        file_type: FileType,
+        replacer: &Replacer,
+    ) -> Result<Option<Self>, ReplaceError> {
+        if !matches!(file_type, FileType::Text | FileType::Code) {
+            return Err(ReplaceError::Unsupported(file_type));
+        }
+        let original = String::from_utf8(fs::read(path)?).map_err(|_| ReplaceError::NotUtf8)?;
+        let (updated, replacements) = replacer.apply(&original)?;
+        if updated == original {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            path: path.to_path_buf(),
+            original,
+            updated,
+            replacements,
+        }))
+    }
+
+    /// The changed lines. When the number of lines is unchanged, each run of changed
+    /// lines is its own hunk; otherwise everything between the unchanged first and
+    /// last lines is one hunk.
+    pub fn hunks(&self) -> Vec<Hunk<'_>> {
+        let old: Vec<&str> = self.original.lines().collect();
+        let new: Vec<&str> = self.updated.lines().collect();
+
+        if old.len() != new.len() {
+            let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+            let suffix = old[prefix..]
+                .iter()
+                .rev()
+                .zip(new[prefix..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            return vec![Hunk {
+                line: prefix + 1,
+                removed: old[prefix..old.len() - suffix].to_vec(),
+                added: new[prefix..new.len() - suffix].to_vec(),
+            }];
+        }
+
+        let mut hunks: Vec<Hunk> = Vec::new();
+        for (i, (a, b)) in old.iter().zip(&new).enumerate() {
+            if a == b {
+                continue;
+            }
+            match hunks.last_mut() {
+                Some(hunk) if hunk.line + hunk.removed.len() == i + 1 => {
+                    hunk.removed.push(a);
+                    hunk.added.push(b);
+                }
+                _ => hunks.push(Hunk {
+                    line: i + 1,
+                    removed: vec![a],
+                    added: vec![b],
+                }),
+            }
+        }
+        hunks
+    }
+
+    /// Write the new content, keeping the original next to it as a backup.
+    /// The content goes to a temporary file that is then renamed over the
+    /// original, so the file is never left half-written. An existing backup is
+    /// never overwritten, since it may hold the only copy of the original.
+    /// Returns the backup's path.
+    pub fn write(&self) -> Result<PathBuf, ReplaceError> {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let backup = self
+            .path
+            .with_file_name(format!("{}{}", file_name, BACKUP_SUFFIX));
+        let temp = self
+            .path
+            .with_file_name(format!(".{}.argus-tmp", file_name));
+
+        let mut backup_file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => ReplaceError::BackupExists(backup.clone()),
+                _ => e.into(),
+            })?;
+        let written = fs::File::open(&self.path)
+            .and_then(|mut original| io::copy(&mut original, &mut backup_file))
+            .and_then(|_| fs::write(&temp, &self.updated))
+            .and_then(|_| fs::set_permissions(&temp, fs::metadata(&self.path)?.permissions()))
+            .and_then(|_| fs::rename(&temp, &self.path));
+        drop(backup_file);
+        if let Err(e) = written {
+            // The backup is ours and may be incomplete; removing it lets the edit be retried
+            let _ = fs::remove_file(&temp);
+            let _ = fs::remove_file(&backup);
+            return Err(e.into());
+        }
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use tempfile::tempdir;
+
+    fn replacer(pattern: &str, replacement: &str, multiline: bool) -> Replacer {
+        let regex = EngineRegex::Standard(Regex::new(pattern).unwrap());
+        Replacer::new(regex, replacement, true, multiline)
+    }
+
+    #[test]
+    fn test_apply_per_line() {
+        let per_line = replacer(r"(\w+)\s+v(\d)", "${1}_v$2", false);
+        let (updated, count) = per_line
+            .apply("api v1\r\nclient\nv2 and api  v3\n")
+            .unwrap();
+        assert_eq!(updated, "api_v1\r\nclient\nv2 and api_v3\n");
+        assert_eq!(count, 2);
+
+        // Without multiline, `\s` does not reach across the line break
+        let (updated, count) = per_line.apply("api\nv1").unwrap();
+        assert_eq!((updated.as_str(), count), ("api\nv1", 0));
+        let multiline = replacer(r"(\w+)\s+v(\d)", "${1}_v$2", true);
+        assert_eq!(multiline.apply("api\nv1").unwrap().0, "api_v1");
+    }
+
+    #[test]
+    fn test_hunks() {
+        let edit = |original: &str, updated: &str| FileEdit {
+            path: PathBuf::new(),
+            original: original.to_string(),
+            updated: updated.to_string(),
+            replacements: 1,
+        };
+        let same_length = edit("a\nb\nc\nd\ne", "A\nB\nc\nD\ne");
+        assert_eq!(
+            same_length.hunks(),
+            vec![
+                Hunk {
+                    line: 1,
+                    removed: vec!["a", "b"],
+                    added: vec!["A", "B"]
+                },
+                Hunk {
+                    line: 4,
+                    removed: vec!["d"],
+                    added: vec!["D"]
+                },
+            ]
+        );
+        let joined = edit("a\nb\nc\nd", "a\nb c\nd");
+        assert_eq!(
+            joined.hunks(),
+            vec![Hunk {
+                line: 2,
+                removed: vec!["b", "c"],
+                added: vec!["b c"]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_and_write() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "old_api here\nand old_api there\n").unwrap();
+        let replacer = replacer("old_api", "new_api", false);
+
+        assert!(matches!(
+            FileEdit::plan(&path, FileType::Pdf, &replacer),
+            Err(ReplaceError::Unsupported(FileType::Pdf))
+        ));
+
+        let edit = FileEdit::plan(&path, FileType::Text, &replacer)
+            .unwrap()
+            .unwrap();
+        assert_eq!(edit.replacements, 2);
+        // Planning does not touch the file
+        assert!(fs::read_to_string(&path).unwrap().starts_with("old_api"));
+
+        let backup = edit.write().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "new_api here\nand new_api there\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&backup).unwrap(),
+            "old_api here\nand old_api there\n"
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        assert!(FileEdit::plan(&path, FileType::Text, &replacer)
+            .unwrap()
+            .is_none());
+        assert!(is_backup(&backup));
+
+        // A second edit leaves the first backup alone
+        fs::write(&path, "old_api again\n").unwrap();
+        let edit = FileEdit::plan(&path, FileType::Text, &replacer)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(edit.write(), Err(ReplaceError::BackupExists(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old_api again\n");
+        assert!(fs::read_to_string(&backup).unwrap().starts_with("old_api here"));
+    }
+
+    #[test]
+    fn test_failed_write_removes_backup() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "old_api\n").unwrap();
+        let edit = FileEdit::plan(&path, FileType::Text, &replacer("old_api", "new_api", false))
+            .unwrap()
+            .unwrap();
+
+        // A directory in the way of the temporary file makes the write fail after the backup
+        let temp = dir.path().join(".notes.txt.argus-tmp");
+        fs::create_dir(&temp).unwrap();
+        fs::write(temp.join("keep"), "").unwrap();
+        assert!(matches!(edit.write(), Err(ReplaceError::Io(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old_api\n");
+        assert!(!dir.path().join("notes.txt.bak").exists());
+
+        // Nothing is left that would block a retry
+        fs::remove_dir_all(&temp).unwrap();
+        let backup = edit.write().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new_api\n");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "old_api\n");
+    }
+}
@@ -1,12 +1,28 @@
 //! Search engine with parallel processing.
 
-use crate::extractors::{extract_text, is_binary_file};
+use crate::extractors::{
+    extract_text, extract_text_with_timeout, is_binary_file, ExtractionOptions, OcrBudget,
+    OCR_LANGUAGE,
+};
+use crate::filters::{extract_qualifiers, FileFilters};
+use crate::fold::{FoldedText, Folding};
 use crate::index::{get_file_timestamp, Index, IndexEntry};
-use crate::types::{FileType, IndexConfig, Match, SearchConfig, SearchResult, SearchStats};
+use crate::names::NameMatcher;
+use crate::ocr_cache::{OcrCache, DEFAULT_CACHE_SIZE};
+use crate::query::{self, Query, QueryError};
+use crate::rank::{Corpus, Ranker};
+use crate::replace::{is_backup, Replacer};
+use crate::stem::{language_names, WordStemmer};
+use crate::types::{
+    ContextLine, FileType, IndexConfig, Match, MatchMode, Ranking, RegexEngine, SearchConfig,
+    SearchResult, SearchStats, SearchTarget,
+};
+use aho_corasick::AhoCorasick;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use regex::{Regex, RegexBuilder};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
@@ -18,31 +34,378 @@ pub struct SearchEngine {
     config: SearchConfig,
     index_config: IndexConfig,
     pattern: SearchPattern,
+    /// Matcher for file names and paths (unless only contents are searched).
+    names: Option<NameMatcher>,
+    /// Filters from qualifiers in the pattern (`type:pdf`, `size:<5MB`, ...).
+    filters: FileFilters,
+    /// Numbers of the capture groups to extract from each match.
+    extract: Vec<usize>,
+    /// Word counts of the texts searched, for BM25 ranking.
+    corpus: Corpus,
     index: Option<Index>,
 }
 
-/// Compiled search pattern (either regex or literal).
+/// Compiled search pattern (regex, literal, fuzzy, several patterns at once,
+/// or a boolean query over those).
 enum SearchPattern {
-    Regex(Regex),
-    Literal { pattern: String, lowercase: String },
+    Regex(EngineRegex),
+    /// Fixed string, folded as configured (see [`folding`]).
+    Literal(String),
+    /// Several literal patterns matched by one Aho-Corasick automaton.
+    MultiLiteral {
+        /// Automaton over the folded patterns.
+        automaton: AhoCorasick,
+        patterns: Vec<Arc<str>>,
+    },
+    /// Several regexes combined into one alternation.
+    MultiRegex {
+        regex: EngineRegex,
+        /// The capture group wrapping each pattern, with the pattern.
+        groups: Vec<(usize, Arc<str>)>,
+    },
+    Query { query: Query, terms: Vec<QueryTerm> },
+    Fuzzy {
+        /// Pattern words, folded as configured.
+        words: Vec<String>,
+        max_distance: usize,
+    },
+    /// Runs of words with the same stems as the pattern's words.
+    Stemmed {
+        stems: Vec<String>,
+        stemmer: WordStemmer,
+    },
 }
 
+/// A compiled term of a boolean query.
+struct QueryTerm {
+    /// The term as written in the query.
+    text: String,
+    /// Pattern matching the term (never itself a query).
+    pattern: SearchPattern,
+}
+
+/// A regex compiled by the configured engine (see [`RegexEngine`]).
+pub(crate) enum EngineRegex {
+    Standard(Regex),
+    Fancy(fancy_regex::Regex),
+    #[cfg(feature = "pcre2")]
+    Pcre2(pcre2::bytes::Regex),
+}
+
+impl EngineRegex {
+    /// Number of capture groups, including the implicit group for the whole match.
+    fn captures_len(&self) -> usize {
+        match self {
+            EngineRegex::Standard(regex) => regex.captures_len(),
+            EngineRegex::Fancy(regex) => regex.captures_len(),
+            #[cfg(feature = "pcre2")]
+            EngineRegex::Pcre2(regex) => regex.captures_len(),
+        }
+    }
+
+    /// Number of a capture group given by number or name, if the regex has it.
+    fn group_number(&self, group: &str) -> Option<usize> {
+        if let Ok(number) = group.parse::<usize>() {
+            return (number < self.captures_len()).then_some(number);
+        }
+        let names: Vec<Option<&str>> = match self {
+            EngineRegex::Standard(regex) => regex.capture_names().collect(),
+            EngineRegex::Fancy(regex) => regex.capture_names().collect(),
+            #[cfg(feature = "pcre2")]
+            EngineRegex::Pcre2(regex) => regex.capture_names().iter().map(Option::as_deref).collect(),
+        };
+        names.iter().position(|name| *name == Some(group))
+    }
+
+    /// Replace every match in `text`, expanding `$1` and `${name}` in the replacement
+    /// if `expand` is set. Returns the new text and the number of replacements.
+    pub(crate) fn replace_all(
+        &self,
+        text: &str,
+        replacement: &str,
+        expand: bool,
+    ) -> Result<(String, usize), String> {
+        match self {
+            EngineRegex::Standard(regex) => {
+                let count = regex.find_iter(text).count();
+                let replaced = if expand {
+                    regex.replace_all(text, replacement)
+                } else {
+                    regex.replace_all(text, regex::NoExpand(replacement))
+                };
+                Ok((replaced.into_owned(), count))
+            }
+            EngineRegex::Fancy(regex) => {
+                let mut count = 0;
+                for mat in regex.find_iter(text) {
+                    mat.map_err(|e| e.to_string())?;
+                    count += 1;
+                }
+                let replaced = if expand {
+                    regex.try_replacen(text, 0, replacement)
+                } else {
+                    regex.try_replacen(text, 0, fancy_regex::NoExpand(replacement))
+                };
+                replaced
+                    .map(|r| (r.into_owned(), count))
+                    .map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "pcre2")]
+            EngineRegex::Pcre2(regex) => {
+                let mut replaced = String::with_capacity(text.len());
+                let mut count = 0;
+                let mut last = 0;
+                for locations in pcre2_matches(regex, text) {
+                    let locations = locations.map_err(|e| e.to_string())?;
+                    let (start, end) = locations.get(0).unwrap_or((last, last));
+                    replaced.push_str(&text[last..start]);
+                    if expand {
+                        let group = |name: &str| {
+                            let number = name.parse::<usize>().ok().or_else(|| {
+                                let names = regex.capture_names();
+                                names.iter().position(|n| n.as_deref() == Some(name))
+                            })?;
+                            let (start, end) = locations.get(number)?;
+                            Some(&text[start..end])
+                        };
+                        expand_replacement(replacement, group, &mut replaced);
+                    } else {
+                        replaced.push_str(replacement);
+                    }
+                    last = end;
+                    count += 1;
+                }
+                replaced.push_str(&text[last..]);
+                Ok((replaced, count))
+            }
+        }
+    }
+}
+
+/// Iterate over the matches of a PCRE2 regex in `haystack`, as the offsets of their
+/// capture groups. Like the `regex` crate, an empty match right after a match is
+/// skipped, and the search moves on by a whole character after an empty match.
+#[cfg(feature = "pcre2")]
+fn pcre2_matches<'a>(
+    regex: &'a pcre2::bytes::Regex,
+    haystack: &'a str,
+) -> impl Iterator<Item = Result<pcre2::bytes::CaptureLocations, pcre2::Error>> + 'a {
+    let mut at = 0;
+    let mut last_end = None;
+    std::iter::from_fn(move || loop {
+        if at > haystack.len() {
+            return None;
+        }
+        let mut locations = regex.capture_locations();
+        let (start, end) = match regex.captures_read_at(&mut locations, haystack.as_bytes(), at) {
+            Ok(Some(mat)) => (mat.start(), mat.end()),
+            Ok(None) => return None,
+            Err(e) => {
+                at = haystack.len() + 1;
+                return Some(Err(e));
+            }
+        };
+        if start == end {
+            at = end + haystack[end..].chars().next().map_or(1, char::len_utf8);
+            if last_end == Some(end) {
+                continue;
+            }
+        } else {
+            at = end;
+        }
+        last_end = Some(end);
+        return Some(Ok(locations));
+    })
+}
+
+/// Append `replacement` to `out`, expanding `$1`, `$name`, `${name}` and `$$` the way
+/// the `regex` crate does. `group` gives the text of a group by number or name;
+/// a group that does not exist or did not participate expands to nothing.
+#[cfg(feature = "pcre2")]
+fn expand_replacement<'t>(
+    replacement: &str,
+    group: impl Fn(&str) -> Option<&'t str>,
+    out: &mut String,
+) {
+    let mut rest = replacement;
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(close) => (&braced[..close], &braced[close + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                rest.split_at(len)
+            }
+        };
+        // A `$` that does not start a group reference is kept as it is
+        if name.is_empty() {
+            out.push('$');
+            continue;
+        }
+        out.push_str(group(name).unwrap_or(""));
+        rest = after;
+    }
+    out.push_str(rest);
+}
+
+/// A regex match found by [`regex_hits`].
+struct RegexHit {
+    /// Byte range of the match in the haystack.
+    range: Range<usize>,
+    /// The pattern it hit, when the regex combines several patterns.
+    pattern: Option<Arc<str>>,
+    /// Values of the requested capture groups.
+    groups: Vec<Option<String>>,
+}
+
+/// Errors that can occur while compiling the search pattern.
+#[derive(Debug)]
+pub enum PatternError {
+    /// Invalid regular expression.
+    Regex(regex::Error),
+    /// Invalid regular expression for the fancy engine.
+    FancyRegex(Box<fancy_regex::Error>),
+    /// Invalid regular expression for the PCRE2 engine.
+    #[cfg(feature = "pcre2")]
+    Pcre2Regex(Box<pcre2::Error>),
+    /// The PCRE2 engine was requested but not built in.
+    #[cfg(not(feature = "pcre2"))]
+    Pcre2NotBuilt,
+    /// Invalid boolean query.
+    Query(QueryError),
+    /// The patterns could not be combined into one automaton.
+    Patterns(aho_corasick::BuildError),
+    /// Invalid glob for matching file names.
+    Glob(globset::Error),
+    /// The pattern consists only of qualifiers.
+    OnlyQualifiers,
+    /// No stemmer for the requested language.
+    UnknownLanguage(String),
+    /// A capture group to extract is not in the pattern.
+    UnknownGroup(String),
+}
+
+impl std::fmt::Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::Regex(e) => {
+                write!(f, "Invalid regex pattern: {}", e)?;
+                let message = e.to_string();
+                if message.contains("look-around") || message.contains("backreferences") {
+                    write!(f, "\n(look-around and backreferences need --engine=fancy)")?;
+                }
+                Ok(())
+            }
+            PatternError::FancyRegex(e) => write!(f, "Invalid regex pattern: {}", e),
+            #[cfg(feature = "pcre2")]
+            PatternError::Pcre2Regex(e) => write!(f, "Invalid regex pattern: {}", e),
+            #[cfg(not(feature = "pcre2"))]
+            PatternError::Pcre2NotBuilt => {
+                write!(f, "The pcre2 engine is not built in. Rebuild with --features pcre2")
+            }
+            PatternError::Query(e) => write!(f, "Invalid query: {}", e),
+            PatternError::Patterns(e) => write!(f, "Invalid patterns: {}", e),
+            PatternError::Glob(e) => write!(f, "Invalid glob pattern: {}", e),
+            PatternError::UnknownLanguage(language) => write!(
+                f,
+                "No stemmer for language '{}'. Supported: {}",
+                language,
+                language_names()
+            ),
+            PatternError::UnknownGroup(group) => {
+                write!(f, "The pattern has no capture group '{}'", group)
+            }
+            PatternError::OnlyQualifiers => {
+                write!(f, "The pattern has only qualifiers; add something to search for")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
 impl SearchEngine {
     /// Create a new search engine with the given configuration.
-    pub fn new(config: SearchConfig, index_config: IndexConfig) -> Result<Self, regex::Error> {
-        let pattern = if config.use_regex {
-            let regex = RegexBuilder::new(&config.pattern)
-                .case_insensitive(!config.case_sensitive)
-                .multi_line(true)
-                .build()?;
-            SearchPattern::Regex(regex)
+    pub fn new(mut config: SearchConfig, index_config: IndexConfig) -> Result<Self, PatternError> {
+        // Qualifiers filter the files to search; the rest of the pattern is searched for.
+        // Regexes are left alone, since `word:` is ordinary regex text. Queries parse
+        // their qualifiers as part of the query.
+        let mut filters = FileFilters::default();
+        if config.qualifiers && !config.use_query && !config.use_regex {
+            let (pattern, found) = extract_qualifiers(&config.pattern);
+            if !found.is_empty() && pattern.trim().is_empty() {
+                if config.patterns.is_empty() {
+                    return Err(PatternError::OnlyQualifiers);
+                }
+                config.pattern = String::new();
+            } else {
+                config.pattern = pattern;
+            }
+            filters = found;
+        }
+
+        let pattern = if config.use_query {
+            let parsed = query::parse(&config.pattern, !config.use_regex)
+                .map_err(PatternError::Query)?;
+            if parsed.terms.is_empty() {
+                return Err(PatternError::OnlyQualifiers);
+            }
+            filters = parsed.filters;
+            let terms = parsed
+                .terms
+                .into_iter()
+                .map(|text| {
+                    let pattern = Self::compile_expanded(&text, &config)?;
+                    Ok(QueryTerm { text, pattern })
+                })
+                .collect::<Result<Vec<_>, PatternError>>()?;
+            SearchPattern::Query {
+                query: parsed.expr,
+                terms,
+            }
         } else {
-            SearchPattern::Literal {
-                pattern: config.pattern.clone(),
-                lowercase: config.pattern.to_lowercase(),
+            let expanded = Self::expand_synonyms(&config.all_patterns(), &config);
+            match &expanded[..] {
+                [] => Self::compile_pattern(&config.pattern, &config)?,
+                [pattern] => Self::compile_pattern(pattern, &config)?,
+                patterns => {
+                    let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+                    Self::compile_multi_pattern(&patterns, &config)?
+                }
             }
         };
 
+        let extract = match &pattern {
+            SearchPattern::Regex(regex) => config
+                .extract
+                .iter()
+                .map(|group| {
+                    regex
+                        .group_number(group)
+                        .ok_or_else(|| PatternError::UnknownGroup(group.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => Vec::new(),
+        };
+
+        let names = match config.target {
+            SearchTarget::Content => None,
+            SearchTarget::Names | SearchTarget::NamesAndContent => Some(
+                NameMatcher::new(&config.pattern, folding(&config)).map_err(PatternError::Glob)?,
+            ),
+        };
+
         // Try to load existing index if use_index is enabled
         let index = if index_config.use_index || index_config.save_index {
             let index_path = index_config.get_index_path(&config.directory);
@@ -68,30 +431,41 @@ impl SearchEngine {
             config,
             index_config,
             pattern,
+            names,
+            filters,
+            extract,
+            corpus: Corpus::default(),
             index,
         })
     }
 
+    /// Create a replacer for the matches of the search pattern. Regex patterns may
+    /// refer to their capture groups in the replacement; fixed strings are replaced
+    /// as they are.
+    pub fn replacer(&self, replacement: &str) -> Result<Replacer, PatternError> {
+        let config = &self.config;
+        let regex = if config.use_regex {
+            Self::build_regex(&config.pattern, config)?
+        } else {
+            Self::build_regex(&regex::escape(&config.pattern), config)?
+        };
+        Ok(Replacer::new(regex, replacement, config.use_regex, config.multiline))
+    }
+
     /// Execute the search and return results.
     pub fn search(&mut self) -> (Vec<SearchResult>, SearchStats) {
         let start = Instant::now();
+        self.corpus = Corpus::default();
 
         // Collect all files to search
-        let files = self.collect_files();
+        let files = self.collect_files(false);
         let total_files = files.len();
 
         // Create progress bar
-        let pb = ProgressBar::new(total_files as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}")
-                .unwrap()
-                .progress_chars("█▓▒░  "),
-        );
-        pb.set_message("Searching...");
+        let pb = progress_bar(total_files, "Searching...");
 
-        // Thread-safe containers for results and stats
-        let results: Arc<Mutex<Vec<SearchResult>>> = Arc::new(Mutex::new(Vec::new()));
+        // Thread-safe containers for results and stats; only the best results are kept
+        let results = Mutex::new(Ranker::new(&self.config));
         let stats = Arc::new(Mutex::new(SearchStats::new()));
         let files_processed = Arc::new(AtomicUsize::new(0));
         let new_index_entries: Arc<Mutex<Vec<IndexEntry>>> = Arc::new(Mutex::new(Vec::new()));
@@ -100,9 +474,12 @@ impl SearchEngine {
         let index_ref = self.index.as_ref().map(|i| Arc::new(i.clone()));
         let save_index = self.index_config.save_index;
 
+        // The OCR budget is shared by every file in this run
+        let options = self.extraction_options();
+
         // Process files in parallel using rayon
         files.par_iter().for_each(|file_path| {
-            let result = self.search_file_with_index(file_path, index_ref.as_ref(), &new_index_entries, save_index);
+            let result = self.search_file_for_target(file_path, index_ref.as_ref(), &new_index_entries, save_index, &options);
 
             // Update stats
             {
@@ -110,19 +487,23 @@ impl SearchEngine {
                 stats_guard.inc_scanned();
 
                 if let Some(ref res) = result {
-                    if res.error.is_some() {
-                        stats_guard.inc_skipped();
+                    if let Some(ref error) = res.error {
+                        stats_guard.add_skipped(&res.path, error);
                     } else {
                         stats_guard.add_result(res);
                     }
                 }
             }
 
-            // Store result if it has matches
+            // Store result if it has matches (or, when inverted, if it was searched and has none)
             if let Some(res) = result {
-                if !res.matches.is_empty() {
-                    let mut results_guard = results.lock().unwrap();
-                    results_guard.push(res);
+                let wanted = if self.config.files_without_match {
+                    !res.is_hit() && res.error.is_none()
+                } else {
+                    res.is_hit()
+                };
+                if wanted {
+                    results.lock().unwrap().push(res);
                 }
             }
 
@@ -134,53 +515,264 @@ impl SearchEngine {
         pb.finish_with_message("Search complete!");
 
         // Update index with new entries if save_index is enabled
-        if self.index_config.save_index {
-            if let Some(ref mut index) = self.index {
-                let entries = Arc::try_unwrap(new_index_entries)
-                    .map(|mutex| mutex.into_inner().unwrap())
-                    .unwrap_or_else(|arc| arc.lock().unwrap().clone());
-
-                for entry in entries {
-                    index.upsert_entry(entry);
-                }
-
-                // Prune entries for files that no longer exist
-                index.prune_missing();
-
-                // Save the index
-                let index_path = self.index_config.get_index_path(&self.config.directory);
-                if let Err(e) = index.save(&index_path) {
-                    eprintln!("  \x1b[33m⚠\x1b[0m Warning: Failed to save index: {}", e);
-                } else {
-                    eprintln!("  \x1b[32m✓\x1b[0m Saved index with {} entries to {}", index.len(), index_path.display());
-                }
-            }
-        }
+        self.save_index(new_index_entries);
 
         // Get final results and stats
-        let mut final_results = Arc::try_unwrap(results)
-            .map(|mutex| mutex.into_inner().unwrap())
-            .unwrap_or_else(|arc| arc.lock().unwrap().clone());
         let mut final_stats = Arc::try_unwrap(stats)
             .map(|mutex| mutex.into_inner().unwrap())
             .unwrap_or_else(|arc| arc.lock().unwrap().clone());
 
-        // Sort results by match count (descending)
-        final_results.sort();
+        // Order and limit the results
+        let final_results = results.into_inner().unwrap().finish(&self.corpus);
 
-        // Limit results
-        if final_results.len() > self.config.limit {
-            final_results.truncate(self.config.limit);
+        // Keep the OCR cache within its size limit
+        if let Some(cache) = &options.ocr_cache {
+            cache.evict();
         }
 
-        // Record duration
+        // Record OCR usage and duration
+        final_stats.ocr_pages = options.ocr_budget.pages_used();
         final_stats.duration_ms = start.elapsed().as_millis() as u64;
 
         (final_results, final_stats)
     }
 
-    /// Collect all files to search based on configuration.
-    fn collect_files(&self) -> Vec<PathBuf> {
+    /// Extract the text of every file to search, using the index like [`Self::search`],
+    /// and map each one with `f` in parallel. Binary files, such as images without OCR,
+    /// are mapped with no text, as are files whose text can't be extracted; the latter
+    /// are also counted as skipped.
+    pub fn map_texts<T: Send>(
+        &mut self,
+        f: impl Fn(&Path, FileType, Option<&str>) -> T + Sync,
+    ) -> (Vec<T>, SearchStats) {
+        let start = Instant::now();
+        let files = self.collect_files(true);
+        let pb = progress_bar(files.len(), "Reading...");
+
+        let mapped: Mutex<Vec<T>> = Mutex::new(Vec::new());
+        let stats = Mutex::new(SearchStats::new());
+        let new_index_entries: Arc<Mutex<Vec<IndexEntry>>> = Arc::new(Mutex::new(Vec::new()));
+        let index_ref = self.index.as_ref().map(|i| Arc::new(i.clone()));
+        let save_index = self.index_config.save_index;
+        let options = self.extraction_options();
+
+        files.par_iter().for_each(|path| {
+            let file_type = file_type_of(path);
+            let text = self.has_text(path).then(|| {
+                self.file_text(
+                    path,
+                    file_type,
+                    index_ref.as_ref(),
+                    &new_index_entries,
+                    save_index,
+                    &options,
+                )
+            });
+            let value = f(path, file_type, text.as_ref().and_then(|t| t.as_deref().ok()));
+            mapped.lock().unwrap().push(value);
+            let mut stats_guard = stats.lock().unwrap();
+            stats_guard.inc_scanned();
+            if let Some(Err(error)) = text {
+                stats_guard.add_skipped(path, &error);
+            }
+            pb.inc(1);
+        });
+
+        pb.finish_with_message("Done!");
+        self.save_index(new_index_entries);
+        if let Some(cache) = &options.ocr_cache {
+            cache.evict();
+        }
+
+        let mut stats = stats.into_inner().unwrap();
+        stats.ocr_pages = options.ocr_budget.pages_used();
+        stats.duration_ms = start.elapsed().as_millis() as u64;
+        (mapped.into_inner().unwrap(), stats)
+    }
+
+    /// Add newly extracted texts to the index and save it, if saving is enabled.
+    fn save_index(&mut self, new_entries: Arc<Mutex<Vec<IndexEntry>>>) {
+        if !self.index_config.save_index {
+            return;
+        }
+        if let Some(ref mut index) = self.index {
+            let entries = Arc::try_unwrap(new_entries)
+                .map(|mutex| mutex.into_inner().unwrap())
+                .unwrap_or_else(|arc| arc.lock().unwrap().clone());
+
+            for entry in entries {
+                index.upsert_entry(entry);
+            }
+
+            // Prune entries for files that no longer exist
+            index.prune_missing();
+
+            // Save the index
+            let index_path = self.index_config.get_index_path(&self.config.directory);
+            if let Err(e) = index.save(&index_path) {
+                eprintln!("  \x1b[33m⚠\x1b[0m Warning: Failed to save index: {}", e);
+            } else {
+                eprintln!("  \x1b[32m✓\x1b[0m Saved index with {} entries to {}", index.len(), index_path.display());
+            }
+        }
+    }
+
+    /// Compile a single regex, fuzzy or literal pattern according to the configuration.
+    fn compile_pattern(pattern: &str, config: &SearchConfig) -> Result<SearchPattern, PatternError> {
+        if let (Some(language), false) = (&config.stem_language, config.use_regex) {
+            let stemmer = WordStemmer::new(language, config.ignore_accents)
+                .ok_or_else(|| PatternError::UnknownLanguage(language.clone()))?;
+            let stems = word_spans(pattern)
+                .into_iter()
+                .map(|(start, end)| stemmer.stem(&pattern[start..end]))
+                .collect();
+            Ok(SearchPattern::Stemmed { stems, stemmer })
+        } else if let (Some(max_distance), false) = (config.fuzzy, config.use_regex) {
+            let pattern = folding(config).fold(pattern);
+            let words = word_spans(&pattern)
+                .into_iter()
+                .map(|(start, end)| pattern[start..end].to_string())
+                .collect();
+            Ok(SearchPattern::Fuzzy {
+                words,
+                max_distance,
+            })
+        } else if config.use_regex {
+            Ok(SearchPattern::Regex(Self::build_regex(pattern, config)?))
+        } else {
+            Ok(SearchPattern::Literal(folding(config).fold(pattern)))
+        }
+    }
+
+    /// Compile a pattern, or an alternation of it and its synonyms if it has any.
+    fn compile_expanded(pattern: &str, config: &SearchConfig) -> Result<SearchPattern, PatternError> {
+        let expanded = Self::expand_synonyms(&[pattern], config);
+        match &expanded[..] {
+            [pattern] => Self::compile_pattern(pattern, config),
+            expansions => {
+                let expansions: Vec<&str> = expansions.iter().map(String::as_str).collect();
+                Self::compile_multi_pattern(&expansions, config)
+            }
+        }
+    }
+
+    /// Follow each pattern with its synonyms, skipping duplicates.
+    fn expand_synonyms(patterns: &[&str], config: &SearchConfig) -> Vec<String> {
+        let mut expanded: Vec<String> = Vec::new();
+        for &pattern in patterns {
+            let terms = match &config.synonyms {
+                Some(synonyms) => synonyms.expand(pattern),
+                None => None,
+            };
+            for term in terms.unwrap_or_else(|| vec![pattern.to_string()]) {
+                if !expanded.contains(&term) {
+                    expanded.push(term);
+                }
+            }
+        }
+        expanded
+    }
+
+    /// Compile several patterns into one matcher that reports which pattern each match hit:
+    /// an Aho-Corasick automaton for literals, or one alternation for regexes.
+    fn compile_multi_pattern(
+        patterns: &[&str],
+        config: &SearchConfig,
+    ) -> Result<SearchPattern, PatternError> {
+        if config.use_regex {
+            // Wrap each pattern in a capture group, numbered after the groups of the patterns before it
+            let mut groups = Vec::with_capacity(patterns.len());
+            let mut wrapped = Vec::with_capacity(patterns.len());
+            let mut next_group = 1;
+            for &pattern in patterns {
+                let own_groups = Self::build_regex(pattern, config)?.captures_len();
+                groups.push((next_group, Arc::from(pattern)));
+                wrapped.push(format!("({})", shift_backreferences(pattern, next_group)));
+                next_group += own_groups;
+            }
+            let alternation = wrapped.join("|");
+            Ok(SearchPattern::MultiRegex {
+                regex: Self::build_regex(&alternation, config)?,
+                groups,
+            })
+        } else {
+            let needles = patterns.iter().map(|p| folding(config).fold(p));
+            Ok(SearchPattern::MultiLiteral {
+                automaton: AhoCorasick::new(needles).map_err(PatternError::Patterns)?,
+                patterns: patterns.iter().map(|&p| Arc::from(p)).collect(),
+            })
+        }
+    }
+
+    /// Build a regex for the pattern, restricted by the configured match mode.
+    fn build_regex(pattern: &str, config: &SearchConfig) -> Result<EngineRegex, PatternError> {
+        let pattern = match config.match_mode {
+            MatchMode::Substring => pattern.to_string(),
+            MatchMode::Word => format!(r"\b(?:{})\b", pattern),
+            MatchMode::Line => format!(r"^(?:{})$", pattern),
+        };
+        match config.regex_engine {
+            RegexEngine::Standard => RegexBuilder::new(&pattern)
+                .case_insensitive(!config.case_sensitive)
+                .multi_line(true)
+                .build()
+                .map(EngineRegex::Standard)
+                .map_err(PatternError::Regex),
+            // The fancy builder has no multi-line option, so set it inline
+            RegexEngine::Fancy => fancy_regex::RegexBuilder::new(&format!("(?m){}", pattern))
+                .case_insensitive(!config.case_sensitive)
+                .build()
+                .map(EngineRegex::Fancy)
+                .map_err(|e| PatternError::FancyRegex(Box::new(e))),
+            // UCP gives `\w`, `\b` and case folding Unicode semantics, as in the other engines
+            #[cfg(feature = "pcre2")]
+            RegexEngine::Pcre2 => pcre2::bytes::RegexBuilder::new()
+                .caseless(!config.case_sensitive)
+                .multi_line(true)
+                .ucp(true)
+                .jit_if_available(true)
+                .build(&pattern)
+                .map(EngineRegex::Pcre2)
+                .map_err(|e| PatternError::Pcre2Regex(Box::new(e))),
+            #[cfg(not(feature = "pcre2"))]
+            RegexEngine::Pcre2 => Err(PatternError::Pcre2NotBuilt),
+        }
+    }
+
+    /// Build extraction options for a search run, starting a fresh OCR budget.
+    fn extraction_options(&self) -> ExtractionOptions {
+        ExtractionOptions {
+            ocr_enabled: self.config.ocr.enabled,
+            ocr_budget: Arc::new(OcrBudget::new(
+                self.config.ocr.max_pages,
+                self.config.ocr.time_budget,
+            )),
+            timeout: self.config.file_timeout,
+            ocr_cache: self.open_ocr_cache().map(Arc::new),
+        }
+    }
+
+    /// Open the shared OCR cache, if OCR and caching are enabled.
+    fn open_ocr_cache(&self) -> Option<OcrCache> {
+        let ocr = &self.config.ocr;
+        if !ocr.enabled {
+            return None;
+        }
+        let dir = ocr.cache_dir.clone()?;
+        let size = ocr.cache_size.unwrap_or(DEFAULT_CACHE_SIZE);
+        let settings = format!("tesseract:{}", OCR_LANGUAGE);
+        match OcrCache::open(dir, size, &settings) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("  \x1b[33m⚠️  Warning: OCR cache unavailable: {}\x1b[0m", e);
+                None
+            }
+        }
+    }
+
+    /// Collect all files to search based on configuration, including binary files
+    /// with no text to search if `binaries` is set.
+    fn collect_files(&self, binaries: bool) -> Vec<PathBuf> {
         let mut walker = WalkDir::new(&self.config.directory);
 
         // Set max depth if specified
@@ -201,6 +793,7 @@ impl SearchEngine {
             .filter_entry(|e| self.should_process_entry(e))
             .filter_map(|e| e.ok())
             .filter(|e| e.file_type().is_file())
+            .filter(|e| !(self.config.skip_backups && is_backup(e.path())))
             .filter(|e| {
                 // Filter by extension if specified
                 if extensions.is_empty() {
@@ -213,24 +806,40 @@ impl SearchEngine {
                 }
             })
             .filter(|e| {
-                // Skip binary files (except PDFs and images which we handle specially)
-                let ext = e
-                    .path()
-                    .extension()
-                    .map(|e| e.to_string_lossy().to_lowercase())
-                    .unwrap_or_default();
-                let file_type = FileType::from_extension(&ext);
-
-                match file_type {
-                    FileType::Pdf | FileType::Docx => true,
-                    FileType::Image => self.config.ocr.enabled,
-                    _ => !is_binary_file(e.path()),
-                }
+                // Qualifiers in the pattern, checked before anything is read
+                let relative = e.path().strip_prefix(&self.config.directory).unwrap_or(e.path());
+                self.filters.accepts_path(relative)
+                    && (!self.filters.needs_metadata()
+                        || e.metadata().is_ok_and(|m| {
+                            self.filters.accepts_metadata(m.len(), m.modified().ok())
+                        }))
             })
+            .filter(|e| binaries || self.has_text(e.path()))
             .map(|e| e.path().to_path_buf())
             .collect()
     }
 
+    /// Whether a file has text to search (or, when searching names, a name).
+    fn has_text(&self, path: &Path) -> bool {
+        // Names are matched without reading the file, so any file will do
+        if self.config.target == SearchTarget::Names {
+            return true;
+        }
+
+        // Skip binary files (except PDFs and images which we handle specially)
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let file_type = FileType::from_extension(&ext);
+
+        match file_type {
+            FileType::Pdf | FileType::Docx | FileType::Pptx => true,
+            FileType::Image => self.config.ocr.enabled,
+            _ => !is_binary_file(path),
+        }
+    }
+
     /// Check if a directory entry should be processed.
     fn should_process_entry(&self, entry: &DirEntry) -> bool {
         // Always process the root directory
@@ -268,80 +877,149 @@ impl SearchEngine {
         true
     }
 
-    /// Search a single file for matches, using the index when available.
-    fn search_file_with_index(
+    /// Search a file's name, its contents, or both, as configured.
+    fn search_file_for_target(
         &self,
         path: &PathBuf,
         index: Option<&Arc<Index>>,
         new_entries: &Arc<Mutex<Vec<IndexEntry>>>,
         save_index: bool,
+        options: &ExtractionOptions,
     ) -> Option<SearchResult> {
-        // Determine file type
+        let Some(names) = &self.names else {
+            return self.search_file_with_index(path, index, new_entries, save_index, options);
+        };
+
+        let name_result = self.match_name(path, names);
+        if self.config.target == SearchTarget::Names {
+            return Some(name_result);
+        }
+
+        match self.search_file_with_index(path, index, new_entries, save_index, options) {
+            Some(mut result) if result.error.is_none() => {
+                result.name_matched = name_result.name_matched;
+                if result.matches.is_empty() {
+                    result.confidence = name_result.confidence;
+                }
+                Some(result)
+            }
+            // A file whose name matched is reported even if its contents could not be read
+            Some(failed) if !name_result.name_matched => Some(failed),
+            _ => Some(name_result),
+        }
+    }
+
+    /// Match the pattern against a file's path relative to the search directory.
+    /// The confidence of a matching name is its match score.
+    fn match_name(&self, path: &Path, names: &NameMatcher) -> SearchResult {
         let ext = path
             .extension()
             .map(|e| e.to_string_lossy().to_string())
             .unwrap_or_default();
         let file_type = FileType::from_extension(&ext);
+        let relative = path.strip_prefix(&self.config.directory).unwrap_or(path);
+
+        let metadata = path.metadata().ok();
+        let file_size = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+        let mut result = SearchResult::new(path.to_path_buf(), file_type, Vec::new(), file_size);
+        result.modified = metadata.and_then(|m| m.modified().ok());
+        if let Some(score) = names.score(relative) {
+            result.name_matched = true;
+            result.confidence = score;
+        }
+        result
+    }
+
+    /// Search a single file for matches, using the index when available.
+    fn search_file_with_index(
+        &self,
+        path: &PathBuf,
+        index: Option<&Arc<Index>>,
+        new_entries: &Arc<Mutex<Vec<IndexEntry>>>,
+        save_index: bool,
+        options: &ExtractionOptions,
+    ) -> Option<SearchResult> {
+        let file_type = file_type_of(path);
 
         // Get file metadata
         let metadata = path.metadata().ok()?;
         let file_size = metadata.len();
-        let modified_timestamp = get_file_timestamp(path).unwrap_or(0);
-
-        // Try to get text from index first
-        let text = if let Some(idx) = index {
-            if let Some(entry) = idx.get_valid_entry(path) {
-                // Use cached text
-                entry.extracted_text.clone()
-            } else {
-                // Extract text and optionally add to index
-                let extraction = extract_text(path, file_type, self.config.ocr.enabled);
-
-                if !extraction.success {
-                    return Some(SearchResult::with_error(
-                        path.clone(),
-                        file_type,
-                        extraction.error.unwrap_or_else(|| "Unknown error".to_string()),
-                    ));
-                }
 
-                // Queue new entry for index if save_index is enabled
-                if save_index {
-                    let entry = IndexEntry::new(
-                        path.clone(),
-                        file_type,
-                        extraction.text.clone(),
-                        modified_timestamp,
-                        file_size,
-                    );
-                    new_entries.lock().unwrap().push(entry);
-                }
+        let text = match self.file_text(path, file_type, index, new_entries, save_index, options) {
+            Ok(text) => text,
+            Err(error) => return Some(SearchResult::with_error(path.clone(), file_type, error)),
+        };
 
-                extraction.text
-            }
+        let word_count = if self.config.ranking == Ranking::Bm25 {
+            let words = text.split_whitespace().count();
+            self.corpus.add(words);
+            words
         } else {
-            // No index - extract text normally
-            let extraction = extract_text(path, file_type, self.config.ocr.enabled);
-
-            if !extraction.success {
-                return Some(SearchResult::with_error(
-                    path.clone(),
-                    file_type,
-                    extraction.error.unwrap_or_else(|| "Unknown error".to_string()),
-                ));
-            }
-
-            extraction.text
+            0
         };
 
         // Search for matches
-        let matches = self.find_matches(&text);
+        let (matches, matched_terms) = self.match_text(&text);
 
         if matches.is_empty() {
-            None
+            // When inverted, a file without hits is what we are looking for
+            self.config.files_without_match.then(|| {
+                let mut result = SearchResult::new(path.clone(), file_type, matches, file_size);
+                result.modified = metadata.modified().ok();
+                result
+            })
         } else {
-            Some(SearchResult::new(path.clone(), file_type, matches, file_size))
+            let mut result = SearchResult::new(path.clone(), file_type, matches, file_size);
+            result.matched_terms = matched_terms;
+            result.modified = metadata.modified().ok();
+            result.word_count = word_count;
+            if self.config.context_before > 0 || self.config.context_after > 0 {
+                result.context_groups = context_groups(
+                    &text,
+                    &result.matches,
+                    self.config.context_before,
+                    self.config.context_after,
+                );
+            }
+            Some(result)
+        }
+    }
+
+    /// Get a file's text from the index, or extract it (queueing it for the index
+    /// if `save_index` is set).
+    fn file_text(
+        &self,
+        path: &PathBuf,
+        file_type: FileType,
+        index: Option<&Arc<Index>>,
+        new_entries: &Arc<Mutex<Vec<IndexEntry>>>,
+        save_index: bool,
+        options: &ExtractionOptions,
+    ) -> Result<String, String> {
+        // Try to get text from index first
+        if let Some(entry) = index.and_then(|idx| idx.get_valid_entry(path)) {
+            return Ok(entry.extracted_text.clone());
+        }
+
+        let extraction = extract_text_with_timeout(path, file_type, options);
+        if !extraction.success {
+            return Err(extraction.error.unwrap_or_else(|| "Unknown error".to_string()));
+        }
+
+        // Queue new entry for index if save_index is enabled
+        // (incomplete extractions are redone next time rather than cached)
+        if index.is_some() && save_index && !extraction.partial {
+            let entry = IndexEntry::new(
+                path.clone(),
+                file_type,
+                extraction.text.clone(),
+                get_file_timestamp(path).unwrap_or(0),
+                path.metadata().map(|m| m.len()).unwrap_or(0),
+            );
+            new_entries.lock().unwrap().push(entry);
         }
+
+        Ok(extraction.text)
     }
 
     /// Search a single file for matches (without index).
@@ -358,7 +1036,7 @@ impl SearchEngine {
         let file_size = path.metadata().map(|m| m.len()).unwrap_or(0);
 
         // Extract text
-        let extraction = extract_text(path, file_type, self.config.ocr.enabled);
+        let extraction = extract_text(path, file_type, &self.extraction_options());
 
         if !extraction.success {
             return Some(SearchResult::with_error(
@@ -369,81 +1047,678 @@ impl SearchEngine {
         }
 
         // Search for matches
-        let matches = self.find_matches(&extraction.text);
+        let (matches, matched_terms) = self.match_text(&extraction.text);
 
         if matches.is_empty() {
             None
         } else {
-            Some(SearchResult::new(path.to_path_buf(), file_type, matches, file_size))
+            let mut result = SearchResult::new(path.to_path_buf(), file_type, matches, file_size);
+            result.matched_terms = matched_terms;
+            Some(result)
         }
     }
 
-    /// Find all matches in the given text.
-    fn find_matches(&self, text: &str) -> Vec<Match> {
+    /// Match the search pattern against a file's text.
+    /// Returns the matches and, for queries and multiple patterns, the terms that matched.
+    fn match_text(&self, text: &str) -> (Vec<Match>, Vec<String>) {
         match &self.pattern {
-            SearchPattern::Regex(regex) => self.find_regex_matches(text, regex),
-            SearchPattern::Literal { pattern, lowercase } => {
-                self.find_literal_matches(text, pattern, lowercase)
+            SearchPattern::Query { query, terms } => self.find_query_matches(text, query, terms),
+            pattern => {
+                let matches = self.find_matches(text, pattern);
+                let mut seen = HashSet::new();
+                let matched_patterns = matches
+                    .iter()
+                    .filter_map(|m| m.pattern.as_deref())
+                    .filter(|p| seen.insert(*p))
+                    .map(str::to_string)
+                    .collect();
+                (matches, matched_patterns)
             }
         }
     }
 
-    /// Find matches using regex.
-    fn find_regex_matches(&self, text: &str, regex: &Regex) -> Vec<Match> {
-        let mut matches = Vec::new();
-        let lines: Vec<&str> = text.lines().collect();
-
-        for line in lines.iter() {
-            for mat in regex.find_iter(line) {
-                matches.push(Match::new(mat.as_str().to_string(), line.to_string()));
+    /// Find all matches of a pattern in the given text.
+    fn find_matches(&self, text: &str, pattern: &SearchPattern) -> Vec<Match> {
+        match pattern {
+            SearchPattern::Regex(regex) => self.find_regex_matches(text, regex, &[]),
+            SearchPattern::MultiRegex { regex, groups } => {
+                self.find_regex_matches(text, regex, groups)
+            }
+            SearchPattern::MultiLiteral {
+                automaton,
+                patterns,
+            } => self.find_multi_literal_matches(text, automaton, patterns),
+            SearchPattern::Literal(pattern) => self.find_literal_matches(text, pattern),
+            SearchPattern::Query { query, terms } => self.find_query_matches(text, query, terms).0,
+            SearchPattern::Fuzzy {
+                words,
+                max_distance,
+            } => self.find_fuzzy_matches(text, words, *max_distance),
+            SearchPattern::Stemmed { stems, stemmer } => {
+                self.find_stemmed_matches(text, stems, stemmer)
             }
         }
-
-        matches
     }
 
-    /// Find matches using literal string search.
-    fn find_literal_matches(&self, text: &str, pattern: &str, lowercase: &str) -> Vec<Match> {
-        let mut matches = Vec::new();
-        let lines: Vec<&str> = text.lines().collect();
+    /// Evaluate a boolean query against the text.
+    /// A file that satisfies the query is reported with the matches of its
+    /// non-negated terms and `NEAR` spans, in text order.
+    fn find_query_matches(
+        &self,
+        text: &str,
+        query: &Query,
+        terms: &[QueryTerm],
+    ) -> (Vec<Match>, Vec<String>) {
+        let term_matches: Vec<Vec<Match>> = terms
+            .iter()
+            .map(|term| self.find_matches(text, &term.pattern))
+            .collect();
 
-        for line in lines.iter() {
-            let search_line = if self.config.case_sensitive {
-                line.to_string()
-            } else {
-                line.to_lowercase()
-            };
+        // Word positions are only needed for NEAR
+        let mut words: Option<Vec<usize>> = None;
+        let leaf_matches: Vec<(&Query, Vec<Match>)> = query
+            .leaves()
+            .into_iter()
+            .map(|leaf| {
+                let matches = match leaf {
+                    Query::Term(term) => term_matches[*term].clone(),
+                    Query::Near {
+                        left,
+                        right,
+                        distance,
+                    } => {
+                        let words = words.get_or_insert_with(|| word_starts(text));
+                        near_matches(
+                            text,
+                            words,
+                            &term_matches[*left],
+                            &term_matches[*right],
+                            *distance,
+                        )
+                    }
+                    Query::And(_) | Query::Or(_) | Query::Not(_) => Vec::new(),
+                };
+                (leaf, matches)
+            })
+            .collect();
 
-            let search_pattern = if self.config.case_sensitive {
-                pattern
-            } else {
-                lowercase
-            };
+        let holds = |leaf: &Query| {
+            leaf_matches
+                .iter()
+                .any(|(l, matches)| *l == leaf && !matches.is_empty())
+        };
+        if !query.eval(&holds) {
+            return (Vec::new(), Vec::new());
+        }
+
+        // Name the synonyms that matched in place of each term, e.g. "PO (purchase order)"
+        let term_texts: Vec<String> = terms
+            .iter()
+            .zip(&term_matches)
+            .map(|(term, matches)| {
+                let mut expansions: Vec<&str> = Vec::new();
+                for pattern in matches.iter().filter_map(|m| m.pattern.as_deref()) {
+                    if pattern != term.text && !expansions.contains(&pattern) {
+                        expansions.push(pattern);
+                    }
+                }
+                if expansions.is_empty() {
+                    term.text.clone()
+                } else {
+                    format!("{} ({})", term.text, expansions.join(", "))
+                }
+            })
+            .collect();
+        let plain_texts: Vec<String> = terms.iter().map(|t| t.text.clone()).collect();
+        let mut matches = Vec::new();
+        let mut matched_terms = Vec::new();
+        for leaf in query.positive_leaves() {
+            if let Some((_, leaf_hits)) = leaf_matches.iter().find(|(l, _)| *l == leaf) {
+                if !leaf_hits.is_empty() {
+                    // Each match reports the term (or NEAR span) it belongs to
+                    let term: Arc<str> = Arc::from(leaf.describe(&plain_texts));
+                    matches.extend(leaf_hits.iter().map(|m| Match {
+                        pattern: Some(term.clone()),
+                        ..m.clone()
+                    }));
+                    matched_terms.push(leaf.describe(&term_texts));
+                }
+            }
+        }
+        matches.sort_by_key(|m| m.byte_offset);
+
+        (matches, matched_terms)
+    }
 
-            let mut start = 0;
-            while let Some(pos) = search_line[start..].find(search_pattern) {
-                let actual_pos = start + pos;
-                let matched_text = &line[actual_pos..actual_pos + pattern.len()];
+    /// Find matches using regex.
+    /// `groups` maps capture groups to patterns when the regex combines several patterns.
+    fn find_regex_matches(
+        &self,
+        text: &str,
+        regex: &EngineRegex,
+        groups: &[(usize, Arc<str>)],
+    ) -> Vec<Match> {
+        if self.config.multiline {
+            return self.find_multiline_matches(text, regex, groups);
+        }
 
-                matches.push(Match::new(matched_text.to_string(), line.to_string()));
+        let mut matches = Vec::new();
 
-                start = actual_pos + 1;
-                if start >= search_line.len() {
-                    break;
-                }
+        for (line_number, line_start, line) in numbered_lines(text) {
+            for hit in regex_hits(regex, line, groups, &self.extract) {
+                let start = hit.range.start;
+                matches.push(Match {
+                    pattern: hit.pattern,
+                    groups: hit.groups,
+                    ..Match::new(
+                        line[hit.range].to_string(),
+                        line.to_string(),
+                        line_number,
+                        column_at(line, start),
+                        line_start + start,
+                    )
+                });
             }
         }
 
         matches
     }
-}
 
-#[cfg(test)]
+    /// Find regex matches over the whole text, so a match can span several lines.
+    /// The context is every line the match touches.
+    fn find_multiline_matches(
+        &self,
+        text: &str,
+        regex: &EngineRegex,
+        groups: &[(usize, Arc<str>)],
+    ) -> Vec<Match> {
+        let lines: Vec<(usize, usize, &str)> = numbered_lines(text).collect();
+        if lines.is_empty() {
+            return Vec::new();
+        }
+        let line_at = |offset: usize| lines.partition_point(|&(_, start, _)| start <= offset) - 1;
+
+        regex_hits(regex, text, groups, &self.extract)
+            .into_iter()
+            .map(|hit| {
+                let (start, end) = (hit.range.start, hit.range.end);
+                let first = line_at(start);
+                // An empty match or one ending in a newline ends on the line it started on
+                let last = line_at(end.saturating_sub(1).max(start));
+                let (line_number, line_start, line) = lines[first];
+                let context = lines[first..=last]
+                    .iter()
+                    .map(|&(_, _, l)| l)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                Match {
+                    end_line: lines[last].0,
+                    pattern: hit.pattern,
+                    groups: hit.groups,
+                    ..Match::new(
+                        text[hit.range].to_string(),
+                        context,
+                        line_number,
+                        // A match can start in a line's `\r\n` or, if empty, at the end of the text
+                        column_at(line, (start - line_start).min(line.len())),
+                        start,
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Find runs of words within `max_distance` edits (Levenshtein) of the pattern's words.
+    /// A run has as many words as the pattern and is compared with single spaces between words.
+    fn find_fuzzy_matches(&self, text: &str, words: &[String], max_distance: usize) -> Vec<Match> {
+        let mut matches = Vec::new();
+        if words.is_empty() {
+            return matches;
+        }
+        let target = words.join(" ");
+        let target_len = target.chars().count();
+
+        for (line_number, line_start, line) in numbered_lines(text) {
+            let spans = word_spans(line);
+            for window in spans.windows(words.len()) {
+                let (start, end) = (window[0].0, window[window.len() - 1].1);
+                let candidate = window
+                    .iter()
+                    .map(|&(s, e)| &line[s..e])
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let candidate = folding(&self.config).fold(&candidate);
+
+                // Cheap length check before computing the edit distance
+                if candidate.chars().count().abs_diff(target_len) > max_distance {
+                    continue;
+                }
+                let distance = strsim::levenshtein(&candidate, &target);
+                if distance <= max_distance {
+                    matches.push(Match {
+                        distance,
+                        ..Match::new(
+                            line[start..end].to_string(),
+                            line.to_string(),
+                            line_number,
+                            column_at(line, start),
+                            line_start + start,
+                        )
+                    });
+                }
+            }
+        }
+
+        matches
+    }
+
+    /// Find runs of words whose stems equal the pattern's stems.
+    /// The match is the words as they appear in the text.
+    fn find_stemmed_matches(
+        &self,
+        text: &str,
+        stems: &[String],
+        stemmer: &WordStemmer,
+    ) -> Vec<Match> {
+        let mut matches = Vec::new();
+        if stems.is_empty() {
+            return matches;
+        }
+
+        for (line_number, line_start, line) in numbered_lines(text) {
+            let spans = word_spans(line);
+            let line_stems: Vec<String> = spans
+                .iter()
+                .map(|&(s, e)| stemmer.stem(&line[s..e]))
+                .collect();
+
+            for (idx, window) in line_stems.windows(stems.len()).enumerate() {
+                if window == stems {
+                    let (start, end) = (spans[idx].0, spans[idx + stems.len() - 1].1);
+                    matches.push(Match::new(
+                        line[start..end].to_string(),
+                        line.to_string(),
+                        line_number,
+                        column_at(line, start),
+                        line_start + start,
+                    ));
+                }
+            }
+        }
+
+        matches
+    }
+
+    /// Find matches using literal string search.
+    /// Lines are folded as configured and hits are mapped back onto the original line.
+    fn find_literal_matches(&self, text: &str, pattern: &str) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let folding = folding(&self.config);
+
+        for (line_number, line_start, line) in numbered_lines(text) {
+            let folded = FoldedText::new(line, folding);
+
+            let mut from = 0;
+            while let Some(pos) = folded.text[from..].find(pattern) {
+                let hit = from + pos;
+
+                if let Some((start, end)) = folded.original_span(hit, hit + pattern.len()) {
+                    if self.config.match_mode.accepts(line, start, end) {
+                        matches.push(Match::new(
+                            line[start..end].to_string(),
+                            line.to_string(),
+                            line_number,
+                            column_at(line, start),
+                            line_start + start,
+                        ));
+                    }
+                }
+
+                // Resume after the first character of the hit so overlapping hits are found
+                from = hit + folded.text[hit..].chars().next().map_or(1, char::len_utf8);
+                if from >= folded.text.len() {
+                    break;
+                }
+            }
+        }
+
+        matches
+    }
+
+    /// Find matches of several literal patterns with one pass of the automaton per line.
+    /// Overlapping hits of different patterns are all reported.
+    fn find_multi_literal_matches(
+        &self,
+        text: &str,
+        automaton: &AhoCorasick,
+        patterns: &[Arc<str>],
+    ) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let folding = folding(&self.config);
+
+        for (line_number, line_start, line) in numbered_lines(text) {
+            let folded = FoldedText::new(line, folding);
+
+            let first = matches.len();
+            for hit in automaton.find_overlapping_iter(&folded.text) {
+                let Some((start, end)) = folded.original_span(hit.start(), hit.end()) else {
+                    continue;
+                };
+                let matched = &line[start..end];
+                if self.config.match_mode.accepts(line, start, end) {
+                    matches.push(Match {
+                        pattern: Some(patterns[hit.pattern().as_usize()].clone()),
+                        ..Match::new(
+                            matched.to_string(),
+                            line.to_string(),
+                            line_number,
+                            column_at(line, start),
+                            line_start + start,
+                        )
+                    });
+                }
+            }
+            // Overlapping hits come out in order of where they end
+            matches[first..].sort_by_key(|m| m.byte_offset);
+        }
+
+        matches
+    }
+}
+
+/// How text is folded before literal and fuzzy comparison.
+fn folding(config: &SearchConfig) -> Folding {
+    Folding {
+        case: !config.case_sensitive,
+        accents: config.ignore_accents,
+    }
+}
+
+/// Add `offset` to the numbered backreferences (`\1`, `\k<1>`) in a pattern, so they
+/// still refer to the pattern's own groups once it is placed after `offset` other groups.
+fn shift_backreferences(pattern: &str, offset: usize) -> String {
+    let mut shifted = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(backslash) = rest.find('\\') {
+        shifted.push_str(&rest[..backslash]);
+        rest = &rest[backslash + 1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let named = rest
+            .strip_prefix("k<")
+            .and_then(|r| Some(r.split_once('>')?.0))
+            .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        if digits > 0 {
+            let group: usize = rest[..digits].parse().unwrap_or(0);
+            shifted.push_str(&format!("\\{}", group + offset));
+            rest = &rest[digits..];
+        } else if let Some(number) = named {
+            let group: usize = number.parse().unwrap_or(0);
+            shifted.push_str(&format!("\\k<{}>", group + offset));
+            rest = &rest[number.len() + 3..];
+        } else {
+            // Any other escape, including `\\`, is copied as is
+            let escaped = rest.chars().next().map_or(0, char::len_utf8);
+            shifted.push('\\');
+            shifted.push_str(&rest[..escaped]);
+            rest = &rest[escaped..];
+        }
+    }
+    shifted.push_str(rest);
+    shifted
+}
+
+/// Detect a file's type from its extension.
+fn file_type_of(path: &Path) -> FileType {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    FileType::from_extension(&ext)
+}
+
+/// Create a progress bar for processing `total` files.
+fn progress_bar(total: usize, message: &'static str) -> ProgressBar {
+    let pb = ProgressBar::new(total as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}")
+            .unwrap()
+            .progress_chars("█▓▒░  "),
+    );
+    pb.set_message(message);
+    pb
+}
+
+/// Find the regex's matches in `haystack`, with the pattern each one hit when the
+/// regex combines several patterns (see [`SearchPattern::MultiRegex`]) and the values
+/// of the capture groups numbered in `extract`.
+fn regex_hits(
+    regex: &EngineRegex,
+    haystack: &str,
+    groups: &[(usize, Arc<str>)],
+    extract: &[usize],
+) -> Vec<RegexHit> {
+    let hit = |group: &dyn Fn(usize) -> Option<Range<usize>>| {
+        Some(RegexHit {
+            range: group(0)?,
+            pattern: groups
+                .iter()
+                .find(|(number, _)| group(*number).is_some())
+                .map(|(_, pattern)| pattern.clone()),
+            groups: extract
+                .iter()
+                .map(|&number| group(number).map(|range| haystack[range].to_string()))
+                .collect(),
+        })
+    };
+    let plain = |range: Range<usize>| RegexHit {
+        range,
+        pattern: None,
+        groups: Vec::new(),
+    };
+    let needs_captures = !groups.is_empty() || !extract.is_empty();
+
+    match regex {
+        EngineRegex::Standard(regex) if !needs_captures => regex
+            .find_iter(haystack)
+            .map(|mat| plain(mat.range()))
+            .collect(),
+        EngineRegex::Standard(regex) => regex
+            .captures_iter(haystack)
+            .filter_map(|caps| hit(&|number| caps.get(number).map(|m| m.range())))
+            .collect(),
+        // Backtracking can fail at run time (e.g. on hitting the backtrack limit);
+        // the hits found before the failure are kept
+        EngineRegex::Fancy(regex) if !needs_captures => regex
+            .find_iter(haystack)
+            .map_while(Result::ok)
+            .map(|mat| plain(mat.range()))
+            .collect(),
+        EngineRegex::Fancy(regex) => regex
+            .captures_iter(haystack)
+            .map_while(Result::ok)
+            .filter_map(|caps| hit(&|number| caps.get(number).map(|m| m.range())))
+            .collect(),
+        // PCRE2 can also fail at run time (e.g. on hitting its match limit)
+        #[cfg(feature = "pcre2")]
+        EngineRegex::Pcre2(regex) => pcre2_matches(regex, haystack)
+            .map_while(Result::ok)
+            .filter_map(|locations| hit(&|number| locations.get(number).map(|(s, e)| s..e)))
+            .collect(),
+    }
+}
+
+/// Iterate over the lines of `text` as (1-based line number, byte offset of the line, line).
+/// Lines are split like [`str::lines`], so a trailing `\r` is not part of the line.
+fn numbered_lines(text: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    text.split_inclusive('\n')
+        .scan(0, |offset, raw| {
+            let start = *offset;
+            *offset += raw.len();
+            let line = raw.strip_suffix('\n').unwrap_or(raw);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            Some((start, line))
+        })
+        .enumerate()
+        .map(|(idx, (start, line))| (idx + 1, start, line))
+}
+
+/// Collect the lines around matches, merging overlapping or adjacent windows into groups.
+fn context_groups(
+    text: &str,
+    matches: &[Match],
+    before: usize,
+    after: usize,
+) -> Vec<Vec<ContextLine>> {
+    let lines: Vec<&str> = numbered_lines(text).map(|(_, _, line)| line).collect();
+    let match_lines: BTreeSet<usize> = matches.iter().flat_map(|m| m.line..=m.end_line).collect();
+
+    // Line windows (1-based, inclusive) around each matching line, merged in order
+    let mut windows: Vec<(usize, usize)> = Vec::new();
+    for &line in &match_lines {
+        let start = line.saturating_sub(before).max(1);
+        let end = (line + after).min(lines.len());
+        match windows.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => windows.push((start, end)),
+        }
+    }
+
+    windows
+        .into_iter()
+        .map(|(start, end)| {
+            (start..=end)
+                .map(|number| ContextLine {
+                    number,
+                    text: lines[number - 1].to_string(),
+                    is_match: match_lines.contains(&number),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Byte ranges of the words (runs of alphanumeric characters) in `text`.
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (pos, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(pos),
+            (false, Some(s)) => {
+                spans.push((s, pos));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+/// Byte offsets at which words start.
+fn word_starts(text: &str) -> Vec<usize> {
+    word_spans(text).into_iter().map(|(start, _)| start).collect()
+}
+
+/// Index of the word containing (or preceding) a byte offset.
+fn word_index(words: &[usize], offset: usize) -> usize {
+    words.partition_point(|&start| start <= offset).saturating_sub(1)
+}
+
+/// Find spans where a `left` match and a `right` match are within `distance` words,
+/// in either order. Each `left` match is paired with its closest `right` match.
+/// The span's text (line breaks folded to spaces) is both the match and its context.
+fn near_matches(
+    text: &str,
+    words: &[usize],
+    left: &[Match],
+    right: &[Match],
+    distance: usize,
+) -> Vec<Match> {
+    // Word range (first, last) and byte range of a match
+    let extent = |m: &Match| {
+        let end = m.byte_offset + m.matched_text.len();
+        let first = word_index(words, m.byte_offset);
+        let last = word_index(words, end.saturating_sub(1).max(m.byte_offset));
+        (first, last, m.byte_offset, end)
+    };
+
+    let mut spans: Vec<Match> = Vec::new();
+    for l in left {
+        let (l_first, l_last, l_start, l_end) = extent(l);
+        let closest = right
+            .iter()
+            // A hit of one term inside a hit of the other is not a pair
+            .filter(|r| r.byte_offset != l.byte_offset)
+            .map(|r| {
+                let (r_first, r_last, _, _) = extent(r);
+                let gap = if r_first > l_last {
+                    r_first - l_last
+                } else {
+                    l_first.saturating_sub(r_last)
+                };
+                (gap, r)
+            })
+            .filter(|(gap, _)| *gap <= distance)
+            .min_by_key(|(gap, _)| *gap);
+
+        if let Some((_, r)) = closest {
+            let (_, _, r_start, r_end) = extent(r);
+            let (start, end) = (l_start.min(r_start), l_end.max(r_end));
+            if spans.iter().any(|m| m.byte_offset == start) {
+                continue;
+            }
+            // The span is reported at the position of whichever hit comes first
+            let first = if r_start < l_start { r } else { l };
+            let span = text[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+            spans.push(Match::new(
+                span.clone(),
+                span,
+                first.line,
+                first.column,
+                start,
+            ));
+        }
+    }
+    spans
+}
+
+/// Convert a byte position within a line to a 1-based character column.
+fn column_at(line: &str, byte_pos: usize) -> usize {
+    line[..byte_pos].chars().count() + 1
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::synonyms::Synonyms;
     use std::fs;
     use tempfile::tempdir;
 
+    /// Search the files in `dir` with `config`.
+    fn search_in(
+        dir: &Path,
+        config: SearchConfig,
+    ) -> Result<(Vec<SearchResult>, SearchStats), PatternError> {
+        let config = SearchConfig {
+            directory: dir.to_path_buf(),
+            ..config
+        };
+        let mut engine = SearchEngine::new(config, IndexConfig::default())?;
+        Ok(engine.search())
+    }
+
+    /// Search a single file holding `text` with `config`.
+    fn search_text(text: &str, config: SearchConfig) -> (Vec<SearchResult>, SearchStats) {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("test.txt"), text).unwrap();
+        search_in(dir.path(), config).unwrap()
+    }
+
     #[test]
     fn test_literal_search() {
         let dir = tempdir().unwrap();
@@ -463,6 +1738,9 @@ mod tests {
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].matches.len(), 2);
         assert_eq!(stats.total_matches, 2);
+        assert_eq!(results[0].matches[1].line, 2);
+        assert_eq!(results[0].matches[1].column, 1);
+        assert_eq!(results[0].matches[1].byte_offset, 12);
     }
 
     #[test]
@@ -506,4 +1784,610 @@ mod tests {
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].matches.len(), 3);
     }
+
+    #[test]
+    fn test_match_positions() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test.txt");
+        fs::write(&file_path, "first line\nnaïve café\n").unwrap();
+
+        let config = SearchConfig {
+            directory: dir.path().to_path_buf(),
+            pattern: "caf".to_string(),
+            case_sensitive: true,
+            ..Default::default()
+        };
+
+        let mut engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let (results, _) = engine.search();
+
+        let m = &results[0].matches[0];
+        assert_eq!((m.line, m.column), (2, 7));
+        assert_eq!(m.byte_offset, "first line\nnaïve ".len());
+        assert_eq!(m.context, "naïve café");
+    }
+
+    #[test]
+    fn test_context_groups_merge() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test.txt");
+        fs::write(&file_path, "a\nhit\nb\nc\nd\ne\nhit\nf\nhit\ng").unwrap();
+
+        let config = SearchConfig {
+            directory: dir.path().to_path_buf(),
+            pattern: "hit".to_string(),
+            context_before: 1,
+            context_after: 1,
+            ..Default::default()
+        };
+
+        let mut engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let (results, _) = engine.search();
+
+        let groups: Vec<Vec<usize>> = results[0]
+            .context_groups
+            .iter()
+            .map(|g| g.iter().map(|l| l.number).collect())
+            .collect();
+        assert_eq!(groups, vec![vec![1, 2, 3], vec![6, 7, 8, 9, 10]]);
+        assert!(results[0].context_groups[1][1].is_match);
+        assert!(!results[0].context_groups[1][2].is_match);
+    }
+
+    #[test]
+    fn test_boolean_query() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "Invoice for 2023\nTotal due").unwrap();
+        fs::write(dir.path().join("b.txt"), "Invoice for 2023 (draft)").unwrap();
+        fs::write(dir.path().join("c.txt"), "Invoice for 2022").unwrap();
+
+        let config = SearchConfig {
+            directory: dir.path().to_path_buf(),
+            pattern: "invoice AND 2023 NOT draft".to_string(),
+            use_query: true,
+            ..Default::default()
+        };
+        let mut engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let (results, _) = engine.search();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].filename(), "a.txt");
+        assert_eq!(results[0].matched_terms, vec!["invoice", "2023"]);
+        assert_eq!(results[0].matches.len(), 2);
+    }
+
+    #[test]
+    fn test_boolean_query_regex_terms() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "receipt 2024-01-05").unwrap();
+        fs::write(dir.path().join("b.txt"), "receipt pending").unwrap();
+
+        let config = SearchConfig {
+            directory: dir.path().to_path_buf(),
+            pattern: r#"(invoice OR receipt) "\d{4}-\d{2}""#.to_string(),
+            use_query: true,
+            use_regex: true,
+            ..Default::default()
+        };
+        let mut engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let (results, _) = engine.search();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].matched_terms, vec!["receipt", r"\d{4}-\d{2}"]);
+    }
+
+    #[test]
+    fn test_near_query_across_lines() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.txt"),
+            "Either party may give notice of\ntermination in writing.",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("b.txt"),
+            "Termination is covered in section 12. Section 30 of this agreement, which is very long, deals with notice.",
+        )
+        .unwrap();
+
+        let config = SearchConfig {
+            directory: dir.path().to_path_buf(),
+            pattern: "termination NEAR/5 notice".to_string(),
+            use_query: true,
+            ..Default::default()
+        };
+        let mut engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let (results, _) = engine.search();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].filename(), "a.txt");
+        assert_eq!(results[0].matched_terms, vec!["termination NEAR/5 notice"]);
+        let span = &results[0].matches[0];
+        assert_eq!(span.context, "notice of termination");
+        assert_eq!((span.line, span.column), (1, 23));
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("exact.txt"), "Run OCR on the scan").unwrap();
+        fs::write(dir.path().join("typo.txt"), "Run 0CR on the scan").unwrap();
+        fs::write(dir.path().join("none.txt"), "Run it on the scan").unwrap();
+
+        let config = SearchConfig {
+            directory: dir.path().to_path_buf(),
+            pattern: "ocr".to_string(),
+            fuzzy: Some(1),
+            ..Default::default()
+        };
+        let mut engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let (results, _) = engine.search();
+
+        assert_eq!(results.len(), 2);
+        let exact = results.iter().find(|r| r.filename() == "exact.txt").unwrap();
+        let typo = results.iter().find(|r| r.filename() == "typo.txt").unwrap();
+        assert_eq!(exact.matches[0].distance, 0);
+        assert_eq!(typo.matches[0].matched_text, "0CR");
+        assert_eq!(typo.matches[0].distance, 1);
+        assert!(exact.confidence > typo.confidence);
+    }
+
+    /// Count matches of `pattern` in `text` with the given options.
+    fn count_matches(text: &str, pattern: &str, use_regex: bool, match_mode: MatchMode) -> usize {
+        let config = SearchConfig {
+            pattern: pattern.to_string(),
+            use_regex,
+            match_mode,
+            ..Default::default()
+        };
+        let (results, _) = search_text(text, config);
+        results.first().map_or(0, |r| r.matches.len())
+    }
+
+    #[test]
+    fn test_word_and_line_modes() {
+        let text = "catalog log_file log\nlog";
+        assert_eq!(count_matches(text, "log", false, MatchMode::Substring), 4);
+        assert_eq!(count_matches(text, "log", false, MatchMode::Word), 2);
+        assert_eq!(count_matches(text, "lo.", true, MatchMode::Word), 2);
+        assert_eq!(count_matches(text, "log", false, MatchMode::Line), 1);
+        assert_eq!(count_matches(text, "l.g", true, MatchMode::Line), 1);
+
+        // Literal mode treats regex metacharacters as plain text
+        assert_eq!(count_matches("a.b(c) axb(c)", "a.b(c)", false, MatchMode::Word), 1);
+    }
+
+    #[test]
+    fn test_multiline_regex() {
+        let config = SearchConfig {
+            pattern: r"BEGIN(?s:.*?)END".to_string(),
+            use_regex: true,
+            multiline: true,
+            ..Default::default()
+        };
+        let (results, _) = search_text("intro\nBEGIN block\nbody\nEND block\nBEGIN alone", config);
+
+        assert_eq!(results[0].matches.len(), 1);
+        let m = &results[0].matches[0];
+        assert_eq!((m.line, m.end_line, m.column), (2, 4, 1));
+        assert_eq!(m.context, "BEGIN block\nbody\nEND block");
+    }
+
+    #[test]
+    fn test_multiline_empty_matches() {
+        let engine = |pattern: &str| {
+            let config = SearchConfig {
+                pattern: pattern.to_string(),
+                use_regex: true,
+                multiline: true,
+                ..Default::default()
+            };
+            SearchEngine::new(config, IndexConfig::default()).unwrap()
+        };
+        let positions = |pattern: &str, text: &str| -> Vec<(usize, usize)> {
+            let (matches, _) = engine(pattern).match_text(text);
+            matches.iter().map(|m| (m.line, m.column)).collect()
+        };
+
+        // An empty match after the final newline is at the end of the last line
+        assert_eq!(positions(r"\z", "ab\n"), vec![(1, 3)]);
+        assert_eq!(positions(r"(?m)^\s*$", "ab\n\ncd\n"), vec![(2, 1), (3, 3)]);
+        assert_eq!(positions("x*", "ab\n").len(), 4);
+        // `$` matches between `\r` and `\n`
+        assert_eq!(positions(r"(?m)b$", "ab\r\ncd\r\n"), Vec::<(usize, usize)>::new());
+        assert_eq!(positions(r"(?m)\r$", "ab\r\ncd\r\n"), vec![(1, 3), (2, 3)]);
+        assert!(positions(r"\z", "").is_empty());
+    }
+
+    #[test]
+    fn test_multiple_patterns() {
+        let run = |use_regex: bool, patterns: &[&str]| {
+            let config = SearchConfig {
+                pattern: patterns[0].to_string(),
+                patterns: patterns[1..].iter().map(|p| p.to_string()).collect(),
+                use_regex,
+                ..Default::default()
+            };
+            search_text("call old_api(id42)\nOLD_API again\nnothing here", config)
+        };
+
+        // Literal patterns share one automaton; overlapping hits are all reported
+        let (results, stats) = run(false, &["old_api", "id42", "api"]);
+        let hits: Vec<(&str, Option<&str>)> = results[0]
+            .matches
+            .iter()
+            .map(|m| (m.matched_text.as_str(), m.pattern.as_deref()))
+            .collect();
+        assert_eq!(
+            hits,
+            vec![
+                ("old_api", Some("old_api")),
+                ("api", Some("api")),
+                ("id42", Some("id42")),
+                ("OLD_API", Some("old_api")),
+                ("API", Some("api")),
+            ]
+        );
+        assert_eq!(results[0].matched_terms, vec!["old_api", "api", "id42"]);
+        assert_eq!(stats.pattern_counts["old_api"], 2);
+        assert_eq!(stats.pattern_counts["id42"], 1);
+
+        // Regex patterns keep their own capture groups without confusing the attribution
+        let (results, stats) = run(true, &[r"(o)ld_(a)pi", r"id(\d+)", "nothing"]);
+        assert_eq!(results[0].matches.len(), 4);
+        assert_eq!(stats.pattern_counts[r"(o)ld_(a)pi"], 2);
+        assert_eq!(stats.pattern_counts[r"id(\d+)"], 1);
+        assert_eq!(stats.pattern_counts["nothing"], 1);
+    }
+
+    #[test]
+    fn test_unicode_folding() {
+        let run = |pattern: &str, ignore_accents: bool| {
+            let config = SearchConfig {
+                pattern: pattern.to_string(),
+                ignore_accents,
+                ..Default::default()
+            };
+            let (results, _) = search_text("İSTANBUL Straẞe\nRésumé and re\u{301}sume\u{301}", config);
+            results
+                .first()
+                .map(|r| {
+                    r.matches
+                        .iter()
+                        .map(|m| (m.matched_text.clone(), m.column))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        // Folding changes byte lengths; spans still map onto the original characters
+        assert_eq!(run("strasse", false), vec![("Straẞe".to_string(), 10)]);
+        assert_eq!(run("stanbul", false), vec![("STANBUL".to_string(), 2)]);
+        assert_eq!(run("résumé", false), vec![("Résumé".to_string(), 1)]);
+
+        // Accent-insensitive matching covers precomposed and decomposed accents
+        assert_eq!(
+            run("resume", true),
+            vec![
+                ("Résumé".to_string(), 1),
+                ("re\u{301}sume\u{301}".to_string(), 12)
+            ]
+        );
+        assert!(run("resume", false).is_empty());
+    }
+
+    #[test]
+    fn test_files_without_match() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("b.txt"), "no header here").unwrap();
+        fs::write(dir.path().join("licensed.rs"), "// Copyright 2024\nfn main() {}").unwrap();
+        fs::write(dir.path().join("a.md"), "# Notes").unwrap();
+
+        let config = SearchConfig {
+            pattern: "copyright".to_string(),
+            files_without_match: true,
+            ..Default::default()
+        };
+        let (results, stats) = search_in(dir.path(), config).unwrap();
+
+        let names: Vec<String> = results.iter().map(|r| r.filename()).collect();
+        assert_eq!(names, vec!["a.md", "b.txt"]);
+        assert!(results.iter().all(|r| r.matches.is_empty()));
+        assert_eq!(stats.files_scanned, 3);
+        assert_eq!(stats.files_matched, 1);
+    }
+
+    #[test]
+    fn test_name_search() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs/2023")).unwrap();
+        fs::write(dir.path().join("docs/2023/invoice_report.txt"), "totals").unwrap();
+        fs::write(dir.path().join("notes.md"), "see the invoice report").unwrap();
+        fs::write(dir.path().join("scan.bin"), [0u8, 159, 146, 150]).unwrap();
+
+        let run = |pattern: &str, target: SearchTarget| {
+            let config = SearchConfig {
+                pattern: pattern.to_string(),
+                target,
+                ..Default::default()
+            };
+            let (results, _) = search_in(dir.path(), config).unwrap();
+            results.iter().map(|r| r.filename()).collect::<Vec<_>>()
+        };
+
+        // Names only: fuzzy or glob, without reading (even binary) files
+        assert_eq!(run("invrep", SearchTarget::Names), vec!["invoice_report.txt"]);
+        assert_eq!(run("*.BIN", SearchTarget::Names), vec!["scan.bin"]);
+        assert_eq!(run("docs/*/*.txt", SearchTarget::Names), vec!["invoice_report.txt"]);
+        assert!(run("invoice report", SearchTarget::Names).is_empty());
+
+        // Combined: notes.md mentions an invoice, invoice_report.txt is named after one
+        let mut both = run("invoice", SearchTarget::NamesAndContent);
+        both.sort();
+        assert_eq!(both, vec!["invoice_report.txt", "notes.md"]);
+        assert_eq!(run("invoice", SearchTarget::Content), vec!["notes.md"]);
+    }
+
+    #[test]
+    fn test_qualifiers_filter_files() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("finance")).unwrap();
+        fs::write(dir.path().join("finance/q1.txt"), "invoice 1").unwrap();
+        fs::write(dir.path().join("finance/q1.md"), "invoice 2").unwrap();
+        fs::write(dir.path().join("other.txt"), "invoice 3").unwrap();
+        fs::write(dir.path().join("finance/big.txt"), "invoice ".repeat(200)).unwrap();
+
+        let run = |pattern: &str| {
+            let config = SearchConfig {
+                pattern: pattern.to_string(),
+                qualifiers: true,
+                ..Default::default()
+            };
+            let (results, stats) = search_in(dir.path(), config)?;
+            let mut names: Vec<String> = results.iter().map(|r| r.filename()).collect();
+            names.sort();
+            Ok::<_, PatternError>((names, stats.files_scanned))
+        };
+
+        let (names, scanned) = run("invoice path:finance/ ext:txt size:<1KB").unwrap();
+        assert_eq!(names, vec!["q1.txt"]);
+        // Filtered files are never opened
+        assert_eq!(scanned, 1);
+
+        let (names, _) = run("type:text modified:>2000-01-01 invoice path:FINANCE").unwrap();
+        assert_eq!(names, vec!["big.txt", "q1.md", "q1.txt"]);
+        assert!(run("invoice modified:<2000-01-01").unwrap().0.is_empty());
+
+        assert!(matches!(run("type:pdf"), Err(PatternError::OnlyQualifiers)));
+        // An invalid value is searched for as text
+        assert!(run("invoice size:huge").unwrap().0.is_empty());
+
+        // Queries take qualifiers as top-level conjuncts
+        let config = SearchConfig {
+            pattern: "(invoice OR receipt) AND ext:txt path:finance NOT huge".to_string(),
+            use_query: true,
+            ..Default::default()
+        };
+        let (results, _) = search_in(dir.path(), config).unwrap();
+        let mut names: Vec<String> = results.iter().map(|r| r.filename()).collect();
+        names.sort();
+        assert_eq!(names, vec!["big.txt", "q1.txt"]);
+    }
+
+    #[test]
+    fn test_stemmed_search() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("guide.txt"),
+            "Configured hosts\nafter configuring the configurations\nreconfigure later",
+        )
+        .unwrap();
+
+        let config = SearchConfig {
+            pattern: "configure hosts".to_string(),
+            stem_language: Some("en".to_string()),
+            ..Default::default()
+        };
+        let (results, _) = search_in(dir.path(), config).unwrap();
+        let hits: Vec<&str> = results[0].matches.iter().map(|m| m.matched_text.as_str()).collect();
+        assert_eq!(hits, vec!["Configured hosts"]);
+
+        let config = SearchConfig {
+            pattern: "configure".to_string(),
+            stem_language: Some("english".to_string()),
+            ..Default::default()
+        };
+        let (results, _) = search_in(dir.path(), config).unwrap();
+        // Surface forms are reported; a different word with the same letters is not
+        let hits: Vec<&str> = results[0].matches.iter().map(|m| m.matched_text.as_str()).collect();
+        assert_eq!(hits, vec!["Configured", "configuring", "configurations"]);
+
+        let config = SearchConfig {
+            pattern: "configure".to_string(),
+            stem_language: Some("klingon".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            SearchEngine::new(config, IndexConfig::default()),
+            Err(PatternError::UnknownLanguage(_))
+        ));
+    }
+
+    #[test]
+    fn test_fancy_engine() {
+        let run = |patterns: &[&str]| {
+            let config = SearchConfig {
+                pattern: patterns[0].to_string(),
+                patterns: patterns[1..].iter().map(|p| p.to_string()).collect(),
+                use_regex: true,
+                regex_engine: RegexEngine::Fancy,
+                ..Default::default()
+            };
+            let (results, _) = search_text("price: 10 USD\nthe the typo\nprice: 20 EUR", config);
+            results[0]
+                .matches
+                .iter()
+                .map(|m| m.matched_text.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Look-ahead, and a backreference combined with another pattern
+        assert_eq!(run(&[r"\d+(?= USD)"]), vec!["10"]);
+        assert_eq!(run(&[r"\b(\w+) \1\b", r"(?<=: )20"]), vec!["the the", "20"]);
+
+        assert_eq!(shift_backreferences(r"(a)\1\k<1>\\1\d", 2), r"(a)\3\k<3>\\1\d");
+
+        // The default engine rejects both
+        let config = SearchConfig {
+            pattern: r"(\w+) \1".to_string(),
+            use_regex: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            SearchEngine::new(config, IndexConfig::default()),
+            Err(PatternError::Regex(_))
+        ));
+    }
+
+    #[cfg(feature = "pcre2")]
+    #[test]
+    fn test_pcre2_engine() {
+        let config = |pattern: &str| SearchConfig {
+            pattern: pattern.to_string(),
+            use_regex: true,
+            regex_engine: RegexEngine::Pcre2,
+            ..Default::default()
+        };
+
+        // Recursion, which neither of the other engines supports
+        let (results, _) = search_text("f(a(b)c) g(x", config(r"\((?:[^()]|(?R))*\)"));
+        assert_eq!(results[0].matches[0].matched_text, "(a(b)c)");
+
+        // Across lines, with `\R` matching either line ending
+        let config_multiline = SearchConfig {
+            multiline: true,
+            ..config(r"begin\R\w+\Rend")
+        };
+        let (results, _) = search_text("BEGIN\r\nbody\nEND", config_multiline);
+        let m = &results[0].matches[0];
+        assert_eq!((m.line, m.end_line), (1, 3));
+
+        // Branch reset numbers both alternatives' groups 1, for --extract
+        let config_extract = SearchConfig {
+            extract: vec!["1".to_string()],
+            ..config(r"(?|id=(\d)|key:(\d))")
+        };
+        let (results, _) = search_text("id=7 key:9", config_extract);
+        let groups: Vec<&Vec<Option<String>>> = results[0].matches.iter().map(|m| &m.groups).collect();
+        assert_eq!(groups, vec![&vec![Some("7".to_string())], &vec![Some("9".to_string())]]);
+
+        // Empty matches step over whole characters
+        let engine = SearchEngine::new(config("x*"), IndexConfig::default()).unwrap();
+        assert_eq!(engine.match_text("é").0.len(), 2);
+
+        let engine = SearchEngine::new(config(r"(?<user>\w+)@(\w+)"), IndexConfig::default()).unwrap();
+        let replacer = engine.replacer("$2 at ${user} ($$1 $3x)").unwrap();
+        assert_eq!(
+            replacer.apply("mail: é@b, c@d").unwrap(),
+            ("mail: b at é ($1 ), d at c ($1 )".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn test_extract_groups() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "INV-17 on 2024-01\nINV-18, INV-17").unwrap();
+        fs::write(dir.path().join("b.txt"), "see INV-18").unwrap();
+
+        let run = |pattern: &str, extract: &[&str]| {
+            let config = SearchConfig {
+                pattern: pattern.to_string(),
+                use_regex: true,
+                extract: extract.iter().map(|g| g.to_string()).collect(),
+                ..Default::default()
+            };
+            search_in(dir.path(), config)
+        };
+
+        // Groups by name and number, in the requested order; missing groups are None
+        let (results, _) = run(r"INV-(?<num>\d+)(?: on (\d+))?", &["2", "num"]).unwrap();
+        let a = results.iter().find(|r| r.path.ends_with("a.txt")).unwrap();
+        let groups: Vec<&Vec<Option<String>>> = a.matches.iter().map(|m| &m.groups).collect();
+        assert_eq!(
+            groups,
+            vec![
+                &vec![Some("2024".to_string()), Some("17".to_string())],
+                &vec![None, Some("18".to_string())],
+                &vec![None, Some("17".to_string())],
+            ]
+        );
+
+        // Distinct values are counted across files
+        let (_, stats) = run(r"INV-(\d+)", &["1"]).unwrap();
+        assert_eq!(stats.value_counts[&vec!["17".to_string()]], 2);
+        assert_eq!(stats.value_counts[&vec!["18".to_string()]], 2);
+
+        assert!(matches!(
+            run(r"INV-(\d+)", &["id"]),
+            Err(PatternError::UnknownGroup(_))
+        ));
+    }
+
+    #[test]
+    fn test_replacer() {
+        // Fixed strings are escaped and replaced as they are, honouring case and word modes
+        let config = SearchConfig {
+            pattern: "v1.0".to_string(),
+            match_mode: MatchMode::Word,
+            ..Default::default()
+        };
+        let engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let replacer = engine.replacer("$1").unwrap();
+        assert_eq!(
+            replacer.apply("V1.0 v1x0 v1.05").unwrap(),
+            ("$1 v1x0 v1.05".to_string(), 1)
+        );
+
+        let config = SearchConfig {
+            pattern: r"(\w+)@(\w+)".to_string(),
+            use_regex: true,
+            ..Default::default()
+        };
+        let engine = SearchEngine::new(config, IndexConfig::default()).unwrap();
+        let replacer = engine.replacer("$2 at $1").unwrap();
+        assert_eq!(replacer.apply("a@b").unwrap(), ("b at a".to_string(), 1));
+    }
+
+    #[test]
+    fn test_synonym_expansion() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "Invoice for purchase order 17").unwrap();
+        fs::write(dir.path().join("b.txt"), "Receipt for P.O. 18").unwrap();
+        let dict_dir = tempdir().unwrap();
+        let dict = dict_dir.path().join("synonyms.toml");
+        fs::write(&dict, "PO = [\"purchase order\", \"P.O.\"]\n").unwrap();
+        let synonyms = Synonyms::load(&dict).unwrap();
+
+        let config = SearchConfig {
+            pattern: "po".to_string(),
+            synonyms: Some(synonyms.clone()),
+            ..Default::default()
+        };
+        let (mut results, _) = search_in(dir.path(), config).unwrap();
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].matched_terms, vec!["purchase order"]);
+        assert_eq!(results[1].matched_terms, vec!["P.O."]);
+
+        // Queries name the expansion that matched in place of the term
+        let config = SearchConfig {
+            pattern: "PO AND invoice".to_string(),
+            use_query: true,
+            synonyms: Some(synonyms),
+            ..Default::default()
+        };
+        let (results, _) = search_in(dir.path(), config).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].matched_terms, vec!["PO (purchase order)", "invoice"]);
+    }
 }
@@ -0,0 +1,99 @@
+//! Snowball stemming for language-aware word matching.
+//!
+//! In stemmed mode, words are compared by their stems, so `configure` also finds
+//! `configured`, `configuring` and `configuration`.
+
+use crate::fold::Folding;
+use rust_stemmers::{Algorithm, Stemmer};
+
+/// Languages with a stemmer, by name and ISO 639-1 code.
+const LANGUAGES: &[(&str, &str, Algorithm)] = &[
+    ("english", "en", Algorithm::English),
+    ("danish", "da", Algorithm::Danish),
+    ("dutch", "nl", Algorithm::Dutch),
+    ("finnish", "fi", Algorithm::Finnish),
+    ("french", "fr", Algorithm::French),
+    ("german", "de", Algorithm::German),
+    ("hungarian", "hu", Algorithm::Hungarian),
+    ("italian", "it", Algorithm::Italian),
+    ("norwegian", "no", Algorithm::Norwegian),
+    ("portuguese", "pt", Algorithm::Portuguese),
+    ("romanian", "ro", Algorithm::Romanian),
+    ("russian", "ru", Algorithm::Russian),
+    ("spanish", "es", Algorithm::Spanish),
+    ("swedish", "sv", Algorithm::Swedish),
+    ("turkish", "tr", Algorithm::Turkish),
+];
+
+/// Reduces words to their stems for one language.
+pub struct WordStemmer {
+    stemmer: Stemmer,
+    /// Whether accents are stripped from the stems.
+    ignore_accents: bool,
+}
+
+impl WordStemmer {
+    /// Create a stemmer for a language given by name or code (e.g. `french` or `fr`).
+    pub fn new(language: &str, ignore_accents: bool) -> Option<Self> {
+        let language = language.to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|(name, code, _)| *name == language || *code == language)
+            .map(|&(_, _, algorithm)| Self {
+                stemmer: Stemmer::create(algorithm),
+                ignore_accents,
+            })
+    }
+
+    /// Stem a word. Stems are always compared case-insensitively; accents are
+    /// stripped after stemming, since the stemmers rely on them.
+    pub fn stem(&self, word: &str) -> String {
+        let lowercase = Folding {
+            case: true,
+            accents: false,
+        }
+        .fold(word);
+        let stem = self.stemmer.stem(&lowercase);
+        Folding {
+            case: false,
+            accents: self.ignore_accents,
+        }
+        .fold(&stem)
+    }
+}
+
+/// Names of the supported languages, for error messages.
+pub fn language_names() -> String {
+    LANGUAGES
+        .iter()
+        .map(|(name, _, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_english_word_forms() {
+        let stemmer = WordStemmer::new("english", false).unwrap();
+        let stem = stemmer.stem("configure");
+        for word in ["configured", "Configuration", "configuring", "CONFIGURES"] {
+            assert_eq!(stemmer.stem(word), stem, "{}", word);
+        }
+        assert_ne!(stemmer.stem("figure"), stem);
+    }
+
+    #[test]
+    fn test_languages_and_accents() {
+        assert!(WordStemmer::new("FR", false).is_some());
+        assert!(WordStemmer::new("klingon", false).is_none());
+
+        let french = WordStemmer::new("french", false).unwrap();
+        assert_eq!(french.stem("continuation"), french.stem("continuations"));
+
+        let german = WordStemmer::new("de", true).unwrap();
+        assert_eq!(german.stem("Häuser"), german.stem("hauser"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Synonym and abbreviation dictionaries.
+//!
+//! A dictionary maps a term to its equivalents, in TOML or JSON:
+//!
+//! ```toml
+//! PO = ["purchase order", "P.O."]
+//! invoice = ["bill"]
+//! ```
+//!
+//! Each entry is a group of interchangeable terms, so searching for any of them
+//! also finds the others. Groups that share a term are merged. Terms are looked
+//! up ignoring case and extra whitespace.
+
+use crate::fold::Folding;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+/// Groups of interchangeable terms.
+#[derive(Debug, Clone, Default)]
+pub struct Synonyms {
+    /// The groups, each in dictionary order (merged groups are empty).
+    groups: Vec<Vec<String>>,
+    /// Group of each normalized term.
+    index: HashMap<String, usize>,
+}
+
+/// Errors that can occur while loading a synonym file.
+#[derive(Debug)]
+pub enum SynonymError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file is not a valid dictionary.
+    Parse(String),
+}
+
+impl fmt::Display for SynonymError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynonymError::Io(e) => write!(f, "{}", e),
+            SynonymError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SynonymError {}
+
+impl Synonyms {
+    /// Load a dictionary; files ending in `.json` are JSON, anything else TOML.
+    pub fn load(path: &Path) -> Result<Self, SynonymError> {
+        let content = std::fs::read_to_string(path).map_err(SynonymError::Io)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let entries: BTreeMap<String, Vec<String>> = if is_json {
+            serde_json::from_str(&content).map_err(|e| SynonymError::Parse(e.to_string()))?
+        } else {
+            toml::from_str(&content).map_err(|e| SynonymError::Parse(e.to_string()))?
+        };
+
+        let mut synonyms = Self::default();
+        for (term, equivalents) in entries {
+            synonyms.add_group(std::iter::once(term).chain(equivalents).collect());
+        }
+        Ok(synonyms)
+    }
+
+    /// Add a group of interchangeable terms, merging it with groups it overlaps.
+    fn add_group(&mut self, terms: Vec<String>) {
+        let target = self.groups.len();
+        self.groups.push(Vec::new());
+
+        for term in terms {
+            let key = normalize(&term);
+            match self.index.get(&key) {
+                Some(&group) if group == target => {}
+                Some(&group) => {
+                    // Move the overlapping group into this one
+                    for moved in std::mem::take(&mut self.groups[group]) {
+                        self.index.insert(normalize(&moved), target);
+                        self.groups[target].push(moved);
+                    }
+                }
+                None => {
+                    self.index.insert(key, target);
+                    self.groups[target].push(term);
+                }
+            }
+        }
+    }
+
+    /// The term followed by its equivalents, or `None` if it has none.
+    pub fn expand(&self, term: &str) -> Option<Vec<String>> {
+        let key = normalize(term);
+        let group = &self.groups[*self.index.get(&key)?];
+        let expansions = std::iter::once(term.to_string())
+            .chain(group.iter().filter(|t| normalize(t) != key).cloned())
+            .collect();
+        Some(expansions)
+    }
+}
+
+/// Normalize a term for lookup: case-folded, with whitespace collapsed.
+fn normalize(term: &str) -> String {
+    let folding = Folding {
+        case: true,
+        accents: false,
+    };
+    folding
+        .fold(term)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_and_expand() {
+        let dir = tempdir().unwrap();
+        let toml_path = dir.path().join("synonyms.toml");
+        fs::write(&toml_path, "PO = [\"purchase order\", \"P.O.\"]\n").unwrap();
+        let json_path = dir.path().join("synonyms.json");
+        fs::write(&json_path, r#"{"PO": ["purchase order", "P.O."]}"#).unwrap();
+
+        for path in [toml_path, json_path] {
+            let synonyms = Synonyms::load(&path).unwrap();
+            assert_eq!(
+                synonyms.expand("Purchase  Order").unwrap(),
+                vec!["Purchase  Order", "PO", "P.O."]
+            );
+            assert_eq!(
+                synonyms.expand("po").unwrap(),
+                vec!["po", "purchase order", "P.O."]
+            );
+            assert!(synonyms.expand("invoice").is_none());
+        }
+    }
+
+    #[test]
+    fn test_overlapping_groups_merge() {
+        let mut synonyms = Synonyms::default();
+        synonyms.add_group(vec!["PO".into(), "purchase order".into()]);
+        synonyms.add_group(vec!["order".into(), "Purchase Order".into()]);
+        assert_eq!(
+            synonyms.expand("order").unwrap(),
+            vec!["order", "PO", "purchase order"]
+        );
+        assert_eq!(
+            synonyms.expand("PO").unwrap(),
+            vec!["PO", "order", "purchase order"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("synonyms.toml");
+        fs::write(&path, "PO = \"not a list\"").unwrap();
+        assert!(matches!(Synonyms::load(&path), Err(SynonymError::Parse(_))));
+        assert!(matches!(
+            Synonyms::load(&dir.path().join("missing.toml")),
+            Err(SynonymError::Io(_))
+        ));
+    }
+}
@@ -1,9 +1,13 @@
 //! Core data types for Argus search tool.
 
+use crate::extractors::{OCR_BUDGET_EXHAUSTED, OCR_NOT_BUILT, OCR_NOT_ENABLED};
+use crate::synonyms::Synonyms;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 /// Represents the type of file being searched.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -16,6 +20,8 @@ pub enum FileType {
     Pdf,
     /// Microsoft Word documents (.docx)
     Docx,
+    /// Microsoft PowerPoint presentations (.pptx)
+    Pptx,
     /// Image files (when OCR is enabled)
     Image,
     /// Unknown/Other file types
@@ -30,6 +36,7 @@ impl FileType {
             FileType::Code => "💻",
             FileType::Pdf => "📕",
             FileType::Docx => "📘",
+            FileType::Pptx => "📙",
             FileType::Image => "🖼️ ",
             FileType::Other => "📎",
         }
@@ -42,6 +49,7 @@ impl FileType {
             FileType::Code => "cyan",
             FileType::Pdf => "red",
             FileType::Docx => "blue",
+            FileType::Pptx => "yellow",
             FileType::Image => "magenta",
             FileType::Other => "white",
         }
@@ -68,6 +76,9 @@ impl FileType {
             // Word documents
             "docx" => FileType::Docx,
 
+            // PowerPoint presentations
+            "pptx" => FileType::Pptx,
+
             // Images
             "png" | "jpg" | "jpeg" | "gif" | "bmp" | "tiff" | "tif" | "webp" => FileType::Image,
 
@@ -84,6 +95,7 @@ impl fmt::Display for FileType {
             FileType::Code => "Code",
             FileType::Pdf => "PDF",
             FileType::Docx => "DOCX",
+            FileType::Pptx => "PPTX",
             FileType::Image => "Image",
             FileType::Other => "Other",
         };
@@ -98,18 +110,57 @@ pub struct Match {
     pub matched_text: String,
     /// Context around the match (the full line or surrounding text).
     pub context: String,
+    /// Line number of the match (1-based).
+    pub line: usize,
+    /// Line number the match ends on (differs from `line` only for multi-line matches).
+    pub end_line: usize,
+    /// Column of the match within its line, in characters (1-based).
+    pub column: usize,
+    /// Byte offset of the match from the start of the (extracted) text.
+    pub byte_offset: usize,
+    /// Edit distance from the pattern (0 for exact matches, set by fuzzy search).
+    pub distance: usize,
+    /// The pattern this match hit, when searching for several patterns at once.
+    pub pattern: Option<Arc<str>>,
+    /// Values of the extracted capture groups, in the requested order
+    /// (None where a group did not participate in the match).
+    pub groups: Vec<Option<String>>,
 }
 
 impl Match {
     /// Create a new match.
-    pub fn new(matched_text: String, context: String) -> Self {
+    pub fn new(
+        matched_text: String,
+        context: String,
+        line: usize,
+        column: usize,
+        byte_offset: usize,
+    ) -> Self {
         Self {
             matched_text,
             context,
+            line,
+            end_line: line,
+            column,
+            byte_offset,
+            distance: 0,
+            pattern: None,
+            groups: Vec::new(),
         }
     }
 }
 
+/// A line shown around matches when context is requested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextLine {
+    /// Line number (1-based).
+    pub number: usize,
+    /// The line's text.
+    pub text: String,
+    /// Whether the line contains a match.
+    pub is_match: bool,
+}
+
 /// Represents a search result for a single file.
 #[derive(Debug, Clone)]
 pub struct SearchResult {
@@ -121,8 +172,20 @@ pub struct SearchResult {
     pub matches: Vec<Match>,
     /// Confidence score (0.0 - 1.0).
     pub confidence: f64,
+    /// Size of the file in bytes.
+    pub file_size: u64,
     /// Error message if extraction partially failed.
     pub error: Option<String>,
+    /// Groups of consecutive lines around matches (empty unless context was requested).
+    pub context_groups: Vec<Vec<ContextLine>>,
+    /// Query terms found in the file (empty unless searching with a boolean query).
+    pub matched_terms: Vec<String>,
+    /// Whether the file's name or path matched the pattern (see [`SearchTarget`]).
+    pub name_matched: bool,
+    /// When the file was last modified, if known.
+    pub modified: Option<SystemTime>,
+    /// Number of words in the file's text (counted only for BM25 ranking).
+    pub word_count: usize,
 }
 
 impl SearchResult {
@@ -134,7 +197,13 @@ impl SearchResult {
             file_type,
             matches,
             confidence,
+            file_size,
             error: None,
+            context_groups: Vec::new(),
+            matched_terms: Vec::new(),
+            name_matched: false,
+            modified: None,
+            word_count: 0,
         }
     }
 
@@ -145,10 +214,21 @@ impl SearchResult {
             file_type,
             matches: Vec::new(),
             confidence: 0.0,
+            file_size: 0,
             error: Some(error),
+            context_groups: Vec::new(),
+            matched_terms: Vec::new(),
+            name_matched: false,
+            modified: None,
+            word_count: 0,
         }
     }
 
+    /// Whether the file matched, by its contents or by its name.
+    pub fn is_hit(&self) -> bool {
+        !self.matches.is_empty() || self.name_matched
+    }
+
     /// Calculate confidence score based on matches and file characteristics.
     fn calculate_confidence(matches: &[Match], file_size: u64) -> f64 {
         if matches.is_empty() {
@@ -171,6 +251,14 @@ impl SearchResult {
         // Combine scores with weights
         let score = (match_score * 0.7) + (density * 0.3);
 
+        // Fuzzy matches count for less: a hit `d` edits away is worth 1 / (1 + d)
+        let exactness = matches
+            .iter()
+            .map(|m| 1.0 / (1.0 + m.distance as f64))
+            .sum::<f64>()
+            / match_count;
+        let score = score * exactness;
+
         // Clamp to 0.0 - 1.0
         score.clamp(0.0, 1.0)
     }
@@ -183,11 +271,12 @@ impl SearchResult {
     /// Get a preview of the first match.
     pub fn preview(&self, max_len: usize) -> Option<String> {
         self.matches.first().map(|m| {
-            let context = m.context.trim();
-            if context.len() > max_len {
-                format!("{}...", &context[..max_len])
+            // Multi-line contexts are shown on one line
+            let context = m.context.split_whitespace().collect::<Vec<_>>().join(" ");
+            if context.chars().count() > max_len {
+                format!("{}...", context.chars().take(max_len).collect::<String>())
             } else {
-                context.to_string()
+                context
             }
         })
     }
@@ -228,17 +317,112 @@ impl PartialOrd for SearchResult {
     }
 }
 
+/// Where a match may occur relative to words and lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Anywhere, including inside longer words.
+    #[default]
+    Substring,
+    /// Only as a whole word (not preceded or followed by a word character).
+    Word,
+    /// Only as a whole line.
+    Line,
+}
+
+impl MatchMode {
+    /// Check whether the byte range `start..end` of `line` is an acceptable match.
+    pub fn accepts(&self, line: &str, start: usize, end: usize) -> bool {
+        match self {
+            MatchMode::Substring => true,
+            MatchMode::Word => {
+                let before = line[..start].chars().next_back();
+                let after = line[end..].chars().next();
+                !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+            }
+            MatchMode::Line => start == 0 && end == line.len(),
+        }
+    }
+}
+
+/// Whether a character is part of a word (letters, digits and underscore, like regex `\w`).
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Regex engine used for `-r` patterns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RegexEngine {
+    /// The `regex` crate: linear-time matching, without look-around or backreferences.
+    #[default]
+    Standard,
+    /// `fancy-regex`: adds look-around and backreferences by backtracking.
+    Fancy,
+    /// PCRE2 (with the `pcre2` feature): full Perl-compatible syntax, such as
+    /// recursion and branch reset groups.
+    Pcre2,
+}
+
+/// How search results are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ranking {
+    /// Most matches first.
+    #[default]
+    Count,
+    /// Most relevant first by BM25, weighing matches against file length and
+    /// how common each term is across the searched files.
+    Bm25,
+    /// Most recently modified first.
+    Recent,
+    /// By path.
+    Path,
+}
+
+/// A field results can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Path,
+    /// Modification time.
+    Modified,
+    /// File size.
+    Size,
+    /// File type (by name).
+    Type,
+    /// Number of matches.
+    Count,
+}
+
+/// Sort results by a field, overriding the [`Ranking`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+/// What the search pattern is matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchTarget {
+    /// The extracted text of each file.
+    #[default]
+    Content,
+    /// File names and paths only; nothing is extracted.
+    Names,
+    /// File names and paths as well as the extracted text.
+    NamesAndContent,
+}
+
 /// OCR configuration options for Tesseract.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct OcrConfig {
     /// Whether OCR is enabled for images and scanned PDFs.
     pub enabled: bool,
-}
-
-impl Default for OcrConfig {
-    fn default() -> Self {
-        Self { enabled: false }
-    }
+    /// Maximum number of pages (images or scanned PDF pages) to OCR per search.
+    pub max_pages: Option<usize>,
+    /// Time after which no new OCR work is started.
+    pub time_budget: Option<Duration>,
+    /// Directory of the shared OCR cache (None = no caching).
+    pub cache_dir: Option<PathBuf>,
+    /// Maximum size of the OCR cache in bytes (None = default size).
+    pub cache_size: Option<u64>,
 }
 
 /// Search configuration options.
@@ -248,12 +432,45 @@ pub struct SearchConfig {
     pub directory: PathBuf,
     /// Search pattern (text or regex).
     pub pattern: String,
+    /// Additional patterns searched for alongside `pattern` (from `--term` or a patterns file).
+    pub patterns: Vec<String>,
     /// Whether the search is case-sensitive.
     pub case_sensitive: bool,
+    /// Whether fixed-string and fuzzy matching ignore accents (`resume` finds `résumé`).
+    pub ignore_accents: bool,
     /// Whether to use regex matching.
     pub use_regex: bool,
+    /// Capture groups to extract from each regex match, by number or name (empty = none).
+    pub extract: Vec<String>,
+    /// Engine that compiles regex patterns.
+    pub regex_engine: RegexEngine,
+    /// Whether the pattern is a boolean query (AND/OR/NOT, parentheses, phrases).
+    pub use_query: bool,
+    /// Whether to skip `.bak` files, the backups kept by `--replace --write`.
+    pub skip_backups: bool,
+    /// Whether qualifiers in the pattern (`type:pdf`) filter the files to search.
+    /// Queries always accept them.
+    pub qualifiers: bool,
+    /// Match words within this Levenshtein distance of the pattern (None = exact matching).
+    pub fuzzy: Option<usize>,
+    /// Match words by their stems in this language (None = no stemming).
+    pub stem_language: Option<String>,
+    /// Synonyms each pattern also matches (None = no expansion).
+    pub synonyms: Option<Synonyms>,
+    /// Restrict matches to whole words or whole lines.
+    pub match_mode: MatchMode,
+    /// Match regexes against the whole text so matches can span lines.
+    pub multiline: bool,
+    /// Report the files whose text has no matches instead of those that do.
+    pub files_without_match: bool,
+    /// Match the pattern against file contents, names, or both.
+    pub target: SearchTarget,
     /// OCR configuration.
     pub ocr: OcrConfig,
+    /// How results are ordered.
+    pub ranking: Ranking,
+    /// Sort results by a field instead of ranking them (None = use `ranking`).
+    pub sort: Option<Sort>,
     /// Maximum number of results to return.
     pub limit: usize,
     /// Maximum directory depth.
@@ -264,6 +481,12 @@ pub struct SearchConfig {
     pub extensions: Vec<String>,
     /// Show content preview.
     pub show_preview: bool,
+    /// Maximum time to spend extracting text from a single file.
+    pub file_timeout: Option<Duration>,
+    /// Lines of context to show before each match.
+    pub context_before: usize,
+    /// Lines of context to show after each match.
+    pub context_after: usize,
 }
 
 impl Default for SearchConfig {
@@ -271,18 +494,47 @@ impl Default for SearchConfig {
         Self {
             directory: PathBuf::from("."),
             pattern: String::new(),
+            patterns: Vec::new(),
             case_sensitive: false,
+            ignore_accents: false,
             use_regex: false,
+            extract: Vec::new(),
+            regex_engine: RegexEngine::Standard,
+            use_query: false,
+            qualifiers: false,
+            skip_backups: false,
+            fuzzy: None,
+            stem_language: None,
+            synonyms: None,
+            match_mode: MatchMode::Substring,
+            multiline: false,
+            files_without_match: false,
+            target: SearchTarget::Content,
             ocr: OcrConfig::default(),
+            ranking: Ranking::Count,
+            sort: None,
             limit: 20,
             max_depth: None,
             include_hidden: false,
             extensions: Vec::new(),
             show_preview: false,
+            file_timeout: None,
+            context_before: 0,
+            context_after: 0,
         }
     }
 }
 
+impl SearchConfig {
+    /// All patterns to search for: `pattern` (if given) followed by `patterns`.
+    pub fn all_patterns(&self) -> Vec<&str> {
+        std::iter::once(self.pattern.as_str())
+            .filter(|p| !p.is_empty())
+            .chain(self.patterns.iter().map(String::as_str))
+            .collect()
+    }
+}
+
 /// Configuration for index file handling.
 #[derive(Debug, Clone, Default)]
 pub struct IndexConfig {
@@ -303,6 +555,23 @@ impl IndexConfig {
     }
 }
 
+/// A file that was skipped during the search, and why.
+#[derive(Debug, Clone)]
+pub struct SkippedFile {
+    /// Path to the file.
+    pub path: PathBuf,
+    /// Why the file was skipped.
+    pub reason: String,
+}
+
+impl SkippedFile {
+    /// Whether the file was skipped by design (OCR off, not built in, or out of
+    /// budget) rather than because reading it failed or timed out.
+    pub fn is_expected(&self) -> bool {
+        [OCR_NOT_ENABLED, OCR_NOT_BUILT, OCR_BUDGET_EXHAUSTED].contains(&self.reason.as_str())
+    }
+}
+
 /// Statistics about the search operation.
 #[derive(Debug, Clone, Default)]
 pub struct SearchStats {
@@ -314,10 +583,18 @@ pub struct SearchStats {
     pub total_matches: usize,
     /// Files skipped due to errors.
     pub files_skipped: usize,
+    /// The skipped files with the reason for skipping each.
+    pub skipped: Vec<SkippedFile>,
+    /// Pages (images or scanned PDF pages) that were OCR'd.
+    pub ocr_pages: usize,
     /// Search duration in milliseconds.
     pub duration_ms: u64,
     /// Breakdown by file type.
     pub by_type: std::collections::HashMap<FileType, usize>,
+    /// Matches per pattern, when searching for several patterns at once.
+    pub pattern_counts: std::collections::HashMap<String, usize>,
+    /// Occurrences of each distinct tuple of extracted capture group values.
+    pub value_counts: std::collections::HashMap<Vec<String>, usize>,
 }
 
 impl SearchStats {
@@ -333,15 +610,26 @@ impl SearchStats {
 
     /// Add a match result.
     pub fn add_result(&mut self, result: &SearchResult) {
-        if !result.matches.is_empty() {
+        if result.is_hit() {
             self.files_matched += 1;
             self.total_matches += result.matches.len();
             *self.by_type.entry(result.file_type).or_insert(0) += 1;
+            for pattern in result.matches.iter().filter_map(|m| m.pattern.as_deref()) {
+                *self.pattern_counts.entry(pattern.to_string()).or_insert(0) += 1;
+            }
+            for m in result.matches.iter().filter(|m| !m.groups.is_empty()) {
+                let values = m.groups.iter().map(|g| g.clone().unwrap_or_default()).collect();
+                *self.value_counts.entry(values).or_insert(0) += 1;
+            }
         }
     }
 
-    /// Increment skipped files.
-    pub fn inc_skipped(&mut self) {
+    /// Record a skipped file.
+    pub fn add_skipped(&mut self, path: &Path, reason: &str) {
         self.files_skipped += 1;
+        self.skipped.push(SkippedFile {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        });
     }
 }
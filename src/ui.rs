@@ -1,17 +1,35 @@
 //! User interface for displaying results and interactive selection.
 
-use crate::types::{SearchResult, SearchStats};
+use crate::dedupe::Cluster;
+use crate::fold::{FoldedText, Folding};
+use crate::replace::{FileEdit, ReplaceError};
+use crate::types::{MatchMode, SearchResult, SearchStats, SkippedFile};
 use colored::*;
 use dialoguer::{theme::ColorfulTheme, Select};
+use std::collections::BTreeMap;
 use std::io::{self, Write};
+use std::path::Path;
 
 /// Characters for the confidence bar.
 const BAR_FILLED: char = '█';
 const BAR_EMPTY: char = '░';
 const BAR_WIDTH: usize = 12;
 
+/// Maximum number of skipped files listed individually.
+const MAX_SKIPPED_SHOWN: usize = 5;
+
+/// Maximum number of per-pattern match counts listed.
+const MAX_PATTERNS_SHOWN: usize = 10;
+
 /// Display the search results in a beautiful format.
-pub fn display_results(results: &[SearchResult], stats: &SearchStats, show_preview: bool) {
+/// With `without_match`, the results are the files that had no matches.
+pub fn display_results(
+    results: &[SearchResult],
+    stats: &SearchStats,
+    show_preview: bool,
+    mode: MatchMode,
+    without_match: bool,
+) {
     // Header
     println!();
     println!();
@@ -21,31 +39,93 @@ pub fn display_results(results: &[SearchResult], stats: &SearchStats, show_previ
     println!();
 
     if results.is_empty() {
-        println!(
-            "{}",
+        let message = if without_match {
+            "  Every searched file contains a match."
+        } else {
             "  No matches found. Try a different search term or directory."
-                .yellow()
-                .italic()
-        );
+        };
+        println!("{}", message.yellow().italic());
         println!();
         return;
     }
 
     // Results
+    let heading = if without_match {
+        format!("{} files without matches:", results.len())
+    } else {
+        format!("{} files with matches:", results.len())
+    };
+    println!("  {} {}", "Found".bright_green(), heading.bright_white().bold());
+    println!();
+
+    for (idx, result) in results.iter().enumerate() {
+        display_result(idx + 1, result, show_preview, mode);
+    }
+
+    println!();
+}
+
+/// Display clusters of duplicate files, largest first.
+pub fn display_clusters(clusters: &[Cluster], stats: &SearchStats) {
+    println!();
+    println!();
+    println!(
+        "  {} {} {} {} {}",
+        "📊".bright_white(),
+        "Stats:".dimmed(),
+        stats.files_scanned.to_string().bright_cyan(),
+        "files compared in".dimmed(),
+        format!("{}ms", stats.duration_ms).bright_magenta()
+    );
+    display_skipped(stats);
+    println!();
+
+    if clusters.is_empty() {
+        println!("{}", "  No duplicates found.".yellow().italic());
+        println!();
+        return;
+    }
+
+    let files: usize = clusters.iter().map(|c| c.files.len()).sum();
     println!(
         "  {} {}",
         "Found".bright_green(),
-        format!("{} files with matches:", results.len())
+        format!("{} groups of duplicates ({} files):", clusters.len(), files)
             .bright_white()
             .bold()
     );
     println!();
 
-    for (idx, result) in results.iter().enumerate() {
-        display_result(idx + 1, result, show_preview);
+    for (idx, cluster) in clusters.iter().enumerate() {
+        let kind = if cluster.exact {
+            "Exact duplicates"
+        } else {
+            "Near-duplicates"
+        };
+        println!(
+            "  {} {} {} {}",
+            format!("#{}", idx + 1).bright_yellow().bold(),
+            kind.bright_white().bold(),
+            "•".dimmed(),
+            format!("{} files", cluster.files.len()).bright_green()
+        );
+        for (n, file) in cluster.files.iter().enumerate() {
+            let similarity = if n == 0 {
+                String::new()
+            } else if file.identical {
+                "identical".to_string()
+            } else {
+                format!("{:.0}% similar", file.similarity * 100.0)
+            };
+            println!(
+                "     {} {} {}",
+                file.file_type.icon(),
+                file.path.to_string_lossy(),
+                similarity.dimmed()
+            );
+        }
+        println!();
     }
-
-    println!();
 }
 
 /// Display search statistics.
@@ -84,10 +164,92 @@ fn display_stats(stats: &SearchStats) {
             type_breakdown.join(" • ").dimmed()
         );
     }
+
+    if stats.ocr_pages > 0 {
+        println!(
+            "  {} {} {}",
+            "🔎".bright_white(),
+            stats.ocr_pages.to_string().bright_cyan(),
+            "pages OCR'd".dimmed()
+        );
+    }
+
+    display_pattern_counts(stats);
+    display_skipped(stats);
+}
+
+/// Show how often each pattern matched, most frequent first.
+fn display_pattern_counts(stats: &SearchStats) {
+    if stats.pattern_counts.is_empty() {
+        return;
+    }
+
+    let mut counts: Vec<(&String, &usize)> = stats.pattern_counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    let shown: Vec<String> = counts
+        .iter()
+        .take(MAX_PATTERNS_SHOWN)
+        .map(|(pattern, count)| format!("{}: {}", pattern, count))
+        .collect();
+    let mut line = shown.join(" • ");
+    if counts.len() > MAX_PATTERNS_SHOWN {
+        line.push_str(&format!(" • ... and {} more", counts.len() - MAX_PATTERNS_SHOWN));
+    }
+
+    println!("  {} {}", "🎯".bright_white(), line.dimmed());
+}
+
+/// List files that failed or timed out, with the reason for each. Files skipped
+/// by design (e.g. images without OCR) are only counted, per reason.
+fn display_skipped(stats: &SearchStats) {
+    if stats.skipped.is_empty() {
+        return;
+    }
+
+    println!(
+        "  {} {} {}",
+        "⏭️ ".bright_white(),
+        stats.files_skipped.to_string().bright_yellow(),
+        "files skipped:".dimmed()
+    );
+
+    let (expected, failed): (Vec<&SkippedFile>, Vec<&SkippedFile>) =
+        stats.skipped.iter().partition(|s| s.is_expected());
+
+    for skipped in failed.iter().take(MAX_SKIPPED_SHOWN) {
+        let name = skipped
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| skipped.path.to_string_lossy().to_string());
+        println!(
+            "     {} {} {}",
+            name.yellow(),
+            "—".dimmed(),
+            skipped.reason.dimmed()
+        );
+    }
+
+    if failed.len() > MAX_SKIPPED_SHOWN {
+        println!(
+            "     {}",
+            format!("... and {} more", failed.len() - MAX_SKIPPED_SHOWN).dimmed()
+        );
+    }
+
+    let mut reasons: BTreeMap<&str, usize> = BTreeMap::new();
+    for skipped in expected {
+        *reasons.entry(skipped.reason.as_str()).or_insert(0) += 1;
+    }
+    for (reason, count) in reasons {
+        let files = if count == 1 { "file" } else { "files" };
+        println!("     {}", format!("{} {} — {}", count, files, reason).dimmed());
+    }
 }
 
 /// Display a single search result.
-fn display_result(rank: usize, result: &SearchResult, show_preview: bool) {
+fn display_result(rank: usize, result: &SearchResult, show_preview: bool, mode: MatchMode) {
     // Rank indicator with special colors for top 3
     let rank_str = match rank {
         1 => format!("#{}", rank).bright_yellow().bold(),
@@ -109,8 +271,12 @@ fn display_result(rank: usize, result: &SearchResult, show_preview: bool) {
         _ => filename.bright_white().bold(),
     };
 
-    // Match count
-    let match_count = format!("{} matches", result.match_count());
+    // Match count, noting a matching file name
+    let match_count = match (result.match_count(), result.name_matched) {
+        (0, true) => "name match".to_string(),
+        (count, true) => format!("{} matches + name", count),
+        (count, false) => format!("{} matches", count),
+    };
 
     // Confidence bar
     let confidence_bar = create_confidence_bar(result.confidence);
@@ -125,6 +291,14 @@ fn display_result(rank: usize, result: &SearchResult, show_preview: bool) {
         path_str.to_string()
     };
 
+    // Files listed for having no matches get no count or confidence
+    if !result.is_hit() {
+        println!("  {} {} {}", rank_str, icon, colored_filename);
+        println!("     {} {}", "📍".dimmed(), display_path.dimmed());
+        println!();
+        return;
+    }
+
     // Print the result
     println!(
         "  {} {} {} {} {} {}",
@@ -136,12 +310,45 @@ fn display_result(rank: usize, result: &SearchResult, show_preview: bool) {
         format!("[{} {}]", confidence_bar, confidence_pct).dimmed()
     );
 
-    println!("     {} {}", "📍".dimmed(), display_path.dimmed());
+    // Point at the first match as path:line:col (plus its end line if it spans lines,
+    // and its byte offset with previews)
+    let location = match result.matches.first() {
+        Some(m) => {
+            let mut location = format!("{}:{}:{}", display_path, m.line, m.column);
+            if m.end_line != m.line {
+                location.push_str(&format!(" (to line {})", m.end_line));
+            }
+            if show_preview {
+                location.push_str(&format!(" (byte {})", m.byte_offset));
+            }
+            location
+        }
+        None => display_path,
+    };
+    println!("     {} {}", "📍".dimmed(), location.dimmed());
 
-    // Show preview if enabled
-    if show_preview {
+    // Explain which query terms matched
+    if !result.matched_terms.is_empty() {
+        let terms: Vec<String> = result
+            .matched_terms
+            .iter()
+            .map(|t| t.bright_cyan().to_string())
+            .collect();
+        println!("     {} {} {}", "🔑".dimmed(), "matched:".dimmed(), terms.join(", "));
+    }
+
+    // Show context groups when requested, otherwise the preview if enabled
+    if !result.context_groups.is_empty() {
+        display_context(result, mode);
+    } else if show_preview {
         if let Some(preview) = result.preview(80) {
-            let highlighted = highlight_match(&preview, &result.matches[0].matched_text);
+            // The preview puts multi-line matches on one line; do the same to the match
+            let matched = result.matches[0]
+                .matched_text
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let highlighted = highlight_match(&preview, &matched, mode);
             println!("     {} {}", "💬".dimmed(), highlighted.italic());
         }
     }
@@ -149,6 +356,39 @@ fn display_result(rank: usize, result: &SearchResult, show_preview: bool) {
     println!();
 }
 
+/// Display the lines around a result's matches, grep-style.
+/// Matching lines are marked with `:`, context lines with `-`, and groups are separated by `--`.
+fn display_context(result: &SearchResult, mode: MatchMode) {
+    for (idx, group) in result.context_groups.iter().enumerate() {
+        if idx > 0 {
+            println!("     {}", "--".dimmed());
+        }
+        for line in group {
+            if line.is_match {
+                let matched = result
+                    .matches
+                    .iter()
+                    .find(|m| m.line == line.number)
+                    .map(|m| m.matched_text.as_str())
+                    .unwrap_or_default();
+                println!(
+                    "     {}{} {}",
+                    format!("{:>5}", line.number).bright_green(),
+                    ":".dimmed(),
+                    highlight_match(&line.text, matched, mode)
+                );
+            } else {
+                println!(
+                    "     {}{} {}",
+                    format!("{:>5}", line.number).dimmed(),
+                    "-".dimmed(),
+                    line.text.dimmed()
+                );
+            }
+        }
+    }
+}
+
 /// Create a visual confidence bar.
 fn create_confidence_bar(confidence: f64) -> String {
     let filled = (confidence * BAR_WIDTH as f64).round() as usize;
@@ -162,31 +402,134 @@ fn create_confidence_bar(confidence: f64) -> String {
 }
 
 /// Highlight matched text in a preview string.
-fn highlight_match(text: &str, pattern: &str) -> String {
+/// The first occurrence allowed by the match mode (e.g. a whole word) is highlighted.
+fn highlight_match(text: &str, pattern: &str, mode: MatchMode) -> String {
     // Case-insensitive search for highlighting
-    let lower_text = text.to_lowercase();
-    let lower_pattern = pattern.to_lowercase();
-
-    if let Some(byte_pos) = lower_text.find(&lower_pattern) {
-        // Map byte position in lowercase back to char count, then slice original by chars
-        let char_start = lower_text[..byte_pos].chars().count();
-        let char_len = lower_pattern.chars().count();
+    let folding = Folding {
+        case: true,
+        accents: false,
+    };
+    let folded = FoldedText::new(text, folding);
+    let pattern = folding.fold(pattern);
 
-        let before: String = text.chars().take(char_start).collect();
-        let matched: String = text.chars().skip(char_start).take(char_len).collect();
-        let after: String = text.chars().skip(char_start + char_len).collect();
+    let found = folded
+        .text
+        .match_indices(&pattern)
+        .filter_map(|(pos, _)| folded.original_span(pos, pos + pattern.len()))
+        .find(|&(start, end)| mode.accepts(text, start, end));
 
+    if let Some((start, end)) = found {
         format!(
             "{}{}{}",
-            before.dimmed(),
-            matched.bright_yellow().bold().underline(),
-            after.dimmed()
+            text[..start].dimmed(),
+            text[start..end].bright_yellow().bold().underline(),
+            text[end..].dimmed()
         )
     } else {
         text.dimmed().to_string()
     }
 }
 
+/// Print the extracted capture groups of every match as tab-separated rows, in path
+/// and line order. Named groups get a header row naming the columns.
+pub fn display_extracted(results: &[SearchResult], columns: &[String]) {
+    if columns.iter().any(|c| c.parse::<usize>().is_err()) {
+        println!("{}", columns.join("\t"));
+    }
+
+    let mut results: Vec<&SearchResult> = results.iter().collect();
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    for m in results.iter().flat_map(|r| &r.matches) {
+        let values: Vec<&str> = m.groups.iter().map(|g| g.as_deref().unwrap_or("")).collect();
+        println!("{}", values.join("\t"));
+    }
+}
+
+/// Print each distinct tuple of extracted values with its number of occurrences,
+/// most frequent first, like `sort | uniq -c`.
+pub fn display_value_counts(stats: &SearchStats) {
+    let mut counts: Vec<(&Vec<String>, &usize)> = stats.value_counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    for (values, count) in counts {
+        println!("{:>7} {}", count, values.join("\t"));
+    }
+}
+
+/// Totals of a replace run.
+#[derive(Debug, Default)]
+pub struct ReplaceSummary {
+    /// Whether the changes were written (otherwise this was a dry run).
+    pub write: bool,
+    /// Files changed (or that would change).
+    pub files: usize,
+    pub replacements: usize,
+    /// Files that could not be edited.
+    pub failed: usize,
+}
+
+/// Show the lines a replacement changes in a file, old lines in red and new in green.
+pub fn display_edit(edit: &FileEdit) {
+    let noun = if edit.replacements == 1 { "replacement" } else { "replacements" };
+    println!(
+        "  {} {} {}",
+        edit.path.to_string_lossy().bright_white().bold(),
+        "•".dimmed(),
+        format!("{} {}", edit.replacements, noun).bright_green()
+    );
+    for hunk in edit.hunks() {
+        for (offset, line) in hunk.removed.iter().enumerate() {
+            let number = format!("{:>6}", hunk.line + offset);
+            println!("  {} {} {}", number.dimmed(), "-".red(), line.red());
+        }
+        for line in &hunk.added {
+            println!("  {} {} {}", " ".repeat(6), "+".green(), line.green());
+        }
+    }
+    println!();
+}
+
+/// Report a file that could not be edited.
+pub fn display_edit_failure(path: &Path, error: &ReplaceError) {
+    println!(
+        "  {} {} {} {}",
+        "⏭️ ".bright_white(),
+        path.to_string_lossy().yellow(),
+        "—".dimmed(),
+        error.to_string().dimmed()
+    );
+    println!();
+}
+
+/// Summarize a replace run.
+pub fn display_replace_summary(summary: &ReplaceSummary) {
+    let totals = format!(
+        "{} replacements in {} files",
+        summary.replacements, summary.files
+    );
+    if summary.write {
+        println!(
+            "  {} {} {}",
+            "✅".bright_green(),
+            "Wrote".bright_green(),
+            format!("{} (originals kept as .bak)", totals).bright_white()
+        );
+    } else {
+        println!(
+            "  {} {} {}",
+            "🔍".bright_white(),
+            "Dry run:".bright_yellow(),
+            format!("{}. Use --write to apply them.", totals).bright_white()
+        );
+    }
+    if summary.failed > 0 {
+        println!(
+            "  {}",
+            format!("{} files could not be edited.", summary.failed).yellow()
+        );
+    }
+    println!();
+}
+
 /// Enter interactive mode for file selection.
 pub fn interactive_select(results: &[SearchResult]) -> Option<&SearchResult> {
     if results.is_empty() {